@@ -0,0 +1,99 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use graph::compile::CompiledField;
+use graph::field::BoundEquation;
+use graph::plot::sample_2d;
+use graph::{marching_cubes, marching_squares, GraphSettings};
+use mathsolver::equation::Equation;
+
+use std::f64::consts::PI;
+
+fn settings(size: u32) -> GraphSettings<'static> {
+    GraphSettings {
+        path: "",
+        image_width: size,
+        image_height: size,
+        sim_window: (-1.0, 1.0, -1.0, 1.0),
+        yaw: 0.5,
+        params: &[],
+        refine: None,
+    }
+}
+
+/// Samples `f` on an `n` by `n` grid over `-1.0..=1.0`.
+fn grid_2d(n: usize, f: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+    let coord = |i: usize| 2.0 * i as f64 / (n - 1) as f64 - 1.0;
+    (0..n * n).map(|i| f(coord(i % n), coord(i / n))).collect()
+}
+
+/// Samples `f` on an `n` by `n` by `n` grid over `-1.0..=1.0`.
+fn grid_3d(n: usize, f: impl Fn(f64, f64, f64) -> f64) -> Vec<f64> {
+    let coord = |i: usize| 2.0 * i as f64 / (n - 1) as f64 - 1.0;
+    (0..n * n * n).map(|i| f(coord(i % n), coord(i / n % n), coord(i / (n * n)))).collect()
+}
+
+fn evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate");
+    group.sample_size(10);
+
+    for equation in ["x^2+y^2=0.5", "sin(5x)=cos(3y)"] {
+        for size in [50, 200] {
+            let settings = settings(size);
+            let mut eq = Equation::new(equation);
+            group.bench_with_input(BenchmarkId::new(format!("{} call_on_custom", equation), size), &size, |b, _| {
+                b.iter(|| sample_2d(&mut BoundEquation::new(&mut eq, &[]), &settings))
+            });
+
+            let mut compiled = CompiledField::parse(equation, &[]).unwrap();
+            group.bench_with_input(BenchmarkId::new(format!("{} compiled", equation), size), &size, |b, _| {
+                b.iter(|| sample_2d(&mut compiled, &settings))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn squares(c: &mut Criterion) {
+    let mut group = c.benchmark_group("marching_squares");
+
+    for n in [101, 401, 1201] {
+        let circle = grid_2d(n, |x, y| x * x + y * y - 0.5);
+        group.bench_with_input(BenchmarkId::new("circle", n), &n, |b, &n| {
+            b.iter_batched(|| circle.clone(), |values| marching_squares(n, n, values), BatchSize::LargeInput)
+        });
+
+        let waves = grid_2d(n, |x, y| (5.0 * PI * x).sin() - (3.0 * PI * y).cos());
+        group.bench_with_input(BenchmarkId::new("waves", n), &n, |b, &n| {
+            b.iter_batched(|| waves.clone(), |values| marching_squares(n, n, values), BatchSize::LargeInput)
+        });
+    }
+
+    group.finish();
+}
+
+fn cubes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("marching_cubes");
+    group.sample_size(20);
+
+    for n in [31, 61] {
+        let fields = [
+            ("sphere", grid_3d(n, |x, y, z| x * x + y * y + z * z - 0.5)),
+            ("torus", grid_3d(n, |x, y, z| ((x * x + z * z).sqrt() - 0.6).powi(2) + y * y - 0.04)),
+            ("gyroid", grid_3d(n, |x, y, z| {
+                let (x, y, z) = (2.0 * PI * x, 2.0 * PI * y, 2.0 * PI * z);
+                x.sin() * y.cos() + y.sin() * z.cos() + z.sin() * x.cos()
+            })),
+        ];
+
+        for (name, values) in &fields {
+            group.bench_with_input(BenchmarkId::new(*name, n), &n, |b, &n| {
+                b.iter_batched(|| values.clone(), |values| marching_cubes(n, n, n, values), BatchSize::LargeInput)
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, evaluation, squares, cubes);
+criterion_main!(benches);
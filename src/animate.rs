@@ -0,0 +1,179 @@
+use mathsolver::equation::Equation;
+use plotters::prelude::*;
+use plotters_bitmap::BitMapBackend;
+
+use std::f64::consts::TAU;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{GraphSettings, Root, create_graph, create_graph_3d};
+use crate::field::BoundEquation;
+use crate::plot::{plot, plot_3d, surface_3d, draw_surface};
+use crate::error::GraphError;
+
+/// A free variable swept over `start..=end` in increments of `step`, parsed from `NAME=START:END:STEP`.
+pub struct Sweep {
+    pub name: String,
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl Sweep {
+    pub fn values(&self) -> impl Iterator<Item = f64> + '_ {
+        let steps = ((self.end - self.start) / self.step + 1e-9).floor() as usize;
+        (0..=steps).map(move |i| self.start + i as f64 * self.step)
+    }
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, range) = s.split_once('=').ok_or_else(|| format!("expected NAME=START:END:STEP, got '{}'", s))?;
+
+        // The name ends up in equations and in the HTML export, so it has to be a plain identifier
+        let is_identifier = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_identifier {
+            return Err(format!("'{}' is not a valid parameter name", name));
+        }
+
+        if ["x", "y", "z"].contains(&name) {
+            return Err(format!("'{}' is already a plotting variable", name));
+        }
+
+        let bounds = range
+            .split(':')
+            .map(|v| v.parse::<f64>().map_err(|e| format!("invalid number '{}': {}", v, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match bounds[..] {
+            [start, end, step] if [start, end, step].iter().any(|v| !v.is_finite()) => Err(format!("'{}' must only contain finite numbers", range)),
+            [start, end, step] if step > 0.0 && start <= end => Ok(Sweep { name: name.to_string(), start, end, step }),
+            [_, _, _] => Err(format!("'{}' must satisfy START <= END and STEP > 0", range)),
+            _ => Err(format!("expected NAME=START:END:STEP, got '{}'", s)),
+        }
+    }
+}
+
+pub fn turntable(eqs: &mut [Equation], settings: &GraphSettings, frames: u32, frame_delay: u32) -> Result<(), GraphError> {
+    // The surfaces don't depend on the camera, so they only have to be sampled once
+    let surfaces: Vec<_> = eqs
+        .iter_mut()
+        .map(|eq| surface_3d(&mut BoundEquation::new(eq, settings.params), settings))
+        .collect();
+
+    let root = BitMapBackend::gif(settings.path, (settings.image_width, settings.image_height), frame_delay)?.into_drawing_area();
+
+    for frame in 0..frames {
+        let frame_settings = GraphSettings {
+            yaw: settings.yaw + TAU * frame as f64 / frames as f64,
+            ..*settings
+        };
+
+        root.fill(&WHITE)?;
+        let mut graph = create_graph_3d(&frame_settings, &root)?;
+
+        for surface in &surfaces {
+            draw_surface(surface, &mut graph)?;
+        }
+
+        root.present()?;
+    }
+
+    Ok(())
+}
+
+/// Renders one frame per value of the swept variable, either into an animated GIF when `settings.path`
+/// ends in `.gif` or into a numbered image sequence (`graph_0000.png`, `graph_0001.png`, ...).
+pub fn sweep(eqs: &mut [Equation], settings: &GraphSettings, sweep: &Sweep, three_d: bool, frame_delay: u32) -> Result<(), GraphError> {
+    let size = (settings.image_width, settings.image_height);
+    let gif_root = match Path::new(settings.path).extension() {
+        Some(ext) if ext == "gif" => Some(BitMapBackend::gif(settings.path, size, frame_delay)?.into_drawing_area()),
+        _ => None,
+    };
+
+    for (frame, value) in sweep.values().enumerate() {
+        let params = [(sweep.name.as_str(), value)];
+        let frame_settings = GraphSettings {
+            params: &params,
+            ..*settings
+        };
+
+        match &gif_root {
+            Some(root) => draw_frame(eqs, &frame_settings, root, three_d)?,
+            None => {
+                let frame_path = numbered_path(settings.path, frame);
+                let root = BitMapBackend::new(&frame_path, size).into_drawing_area();
+                draw_frame(eqs, &frame_settings, &root, three_d)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn draw_frame(eqs: &mut [Equation], settings: &GraphSettings, root: &Root, three_d: bool) -> Result<(), GraphError> {
+    root.fill(&WHITE)?;
+
+    if three_d {
+        let mut graph = create_graph_3d(settings, root)?;
+        for eq in eqs.iter_mut() {
+            plot_3d(eq, settings, &mut graph)?;
+        }
+    } else {
+        let mut graph = create_graph(settings, root)?;
+        for eq in eqs.iter_mut() {
+            plot(eq, settings, &mut graph)?;
+        }
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+fn numbered_path(path: &str, frame: usize) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    path.with_file_name(format!("{}_{:04}.{}", stem, frame, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sweeps() {
+        let sweep: Sweep = "a=0:1:0.25".parse().unwrap();
+        assert_eq!(sweep.name, "a");
+        assert_eq!(sweep.values().collect::<Vec<_>>(), [0.0, 0.25, 0.5, 0.75, 1.0]);
+
+        // The end is included even when rounding leaves the last step just short of it
+        assert_eq!("freq=0:0.3:0.1".parse::<Sweep>().unwrap().values().count(), 4);
+    }
+
+    #[test]
+    fn rejects_malformed_sweeps() {
+        for s in ["a", "a=0:1", "a=0:1:0.1:2", "a=0:x:1", "=0:1:1", "2a=0:1:1", "a-b=0:1:1", "x=0:1:1", "a=1:0:1", "a=0:1:0", "a=0:1:-1"] {
+            assert!(s.parse::<Sweep>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn names_must_be_identifiers() {
+        // Names end up in the attributes of the HTML export
+        for name in ["a\"b", "<a>", "a b", "a&b"] {
+            assert!(format!("{}=0:1:1", name).parse::<Sweep>().is_err(), "{} was accepted", name);
+        }
+        assert!("a_2=0:1:1".parse::<Sweep>().is_ok());
+    }
+
+    #[test]
+    fn rejects_non_finite_bounds() {
+        for s in ["a=0:inf:1", "a=-inf:0:1", "a=0:1:inf", "a=0:NaN:1", "a=NaN:1:1"] {
+            assert!(s.parse::<Sweep>().is_err(), "{} was accepted", s);
+        }
+    }
+}
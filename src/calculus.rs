@@ -0,0 +1,287 @@
+use plotters::prelude::*;
+
+use std::iter;
+use std::str::FromStr;
+
+use crate::{ChartOn, GraphSettings};
+use crate::field::Function;
+use crate::plot::{draw_function, sample_xs};
+use crate::error::GraphError;
+use crate::report::MissingLine;
+
+/// How accurately areas are computed, relative to their size or absolutely for areas below one.
+const AREA_TOLERANCE: f64 = 1e-10;
+
+/// How often `integrate` may halve an interval.
+const MAX_DEPTH: u32 = 40;
+
+/// How often `integrate` may evaluate the function, which bounds the work spent near
+/// singularities and on functions too noisy to ever meet the tolerance.
+const MAX_EVALUATIONS: usize = 100_000;
+
+/// Views of a function drawn on top of it by `plot_function`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overlays {
+    /// Draws `f'(x)` in blue.
+    pub derivative: bool,
+    /// Draws the integral of `f` from this x in green.
+    pub integral: Option<f64>,
+    /// Draws the tangent lines at these x in red.
+    pub tangents: Vec<f64>,
+    /// Draws the normal lines at these x in magenta.
+    pub normals: Vec<f64>,
+    /// Shades the area under the functions over this range and reports its size.
+    pub shade: Option<Span>,
+    /// Shades the area between the first two functions instead of under each of them.
+    pub shade_between: bool,
+    /// Marks the roots, extrema and inflection points and reports a table of them.
+    pub features: bool,
+}
+
+/// A range of x, parsed from `START:END`. The end may be left of the start, which flips the sign
+/// of areas over it like it does for integrals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl FromStr for Span {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').ok_or_else(|| format!("expected START:END, got '{}'", s))?;
+        let number = |v: &str| v.parse::<f64>().map_err(|e| format!("invalid number '{}': {}", v, e));
+        Ok(Span { start: number(start)?, end: number(end)? })
+    }
+}
+
+/// Draws the overlays of a function sampled at `xs`, which has to be sorted. Returns the tangents
+/// and normals that were left out since the function has no value or slope there.
+pub fn draw_overlays<F: Function + ?Sized, DB: DrawingBackend>(function: &mut F, xs: &[f64], overlays: &Overlays, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<Vec<MissingLine>, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let h = difference_step(settings);
+
+    if overlays.derivative {
+        let points: Vec<_> = xs.iter().map(|&x| (x, function.slope(x, h))).collect();
+        draw_function(&points, BLUE.stroke_width(2), chart)?;
+    }
+
+    if let Some(origin) = overlays.integral {
+        draw_function(&running_integral(function, xs, origin), GREEN.stroke_width(2), chart)?;
+    }
+
+    let mut missing = Vec::new();
+    let lines = overlays.tangents.iter().map(|&x| (x, false, &RED)).chain(overlays.normals.iter().map(|&x| (x, true, &MAGENTA)));
+    for (x, normal, color) in lines {
+        if !draw_line(function, x, normal, settings, color, chart)? {
+            missing.push(MissingLine { x, normal });
+        }
+    }
+
+    Ok(missing)
+}
+
+/// The step used for numeric derivatives, small against the window but far above rounding.
+pub fn difference_step(settings: &GraphSettings) -> f64 {
+    (settings.sim_window.1 - settings.sim_window.0) * 1e-6
+}
+
+/// The integral of `function` from `origin` to each of `xs`, which has to be sorted, by Simpson's
+/// rule between neighbouring points. The origin can be far from the nearest point, so the stretch
+/// up to it is integrated adaptively. It is `NaN` beyond any point where the function isn't
+/// defined, since the integral can't be continued past it.
+pub fn running_integral<F: Function + ?Sized>(function: &mut F, xs: &[f64], origin: f64) -> Vec<(f64, f64)> {
+    let mut integral = vec![f64::NAN; xs.len()];
+    let split = xs.partition_point(|&x| x < origin);
+
+    // Outwards from the origin in both directions
+    let mut sum = 0.0;
+    let mut from = origin;
+    for (i, (&x, value)) in xs[split..].iter().zip(&mut integral[split..]).enumerate() {
+        sum += if i == 0 { integrate(function, from, x) } else { simpson(function, from, x) };
+        *value = sum;
+        from = x;
+    }
+
+    let mut sum = 0.0;
+    let mut from = origin;
+    for (i, (&x, value)) in xs[..split].iter().zip(&mut integral[..split]).rev().enumerate() {
+        sum += if i == 0 { integrate(function, from, x) } else { simpson(function, from, x) };
+        *value = sum;
+        from = x;
+    }
+
+    xs.iter().copied().zip(integral).collect()
+}
+
+/// Simpson's rule over `a..b` as a single panel.
+fn simpson<F: Function + ?Sized>(function: &mut F, a: f64, b: f64) -> f64 {
+    (b - a) / 6.0 * (function.evaluate(a) + 4.0 * function.evaluate((a + b) / 2.0) + function.evaluate(b))
+}
+
+/// Fills the region between `upper` and `lower`, or the x-axis if there is no `lower`, over
+/// `span` and returns its signed area, positive where `upper` is above.
+pub fn shade<DB: DrawingBackend>(upper: &mut dyn Function, mut lower: Option<&mut dyn Function>, span: Span, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<f64, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let (start, end) = (span.start.min(span.end), span.start.max(span.end));
+    let inner = sample_xs(settings).into_iter().filter(|&x| x > start && x < end);
+    let xs: Vec<f64> = iter::once(start).chain(inner).chain(iter::once(end)).collect();
+
+    let mut lower_at = |x: f64| lower.as_mut().map_or(0.0, |lower| lower.evaluate(x));
+    let points: Vec<_> = xs.iter().map(|&x| (x, upper.evaluate(x), lower_at(x))).collect();
+
+    // Every stretch where both are defined is filled on its own
+    for run in points.split(|(_, a, b)| !a.is_finite() || !b.is_finite()).filter(|run| run.len() > 1) {
+        let outline: Vec<_> = run.iter().map(|&(x, a, _)| (x, a)).chain(run.iter().rev().map(|&(x, _, b)| (x, b))).collect();
+        chart.draw_series(iter::once(Polygon::new(outline, BLUE.mix(0.2).filled())))?;
+    }
+
+    Ok(integrate(&mut |x: f64| upper.evaluate(x) - lower_at(x), span.start, span.end))
+}
+
+/// The integral of `function` from `a` to `b` by adaptive Simpson's rule, which halves the
+/// intervals only where the function needs it. Not finite if the function isn't defined or
+/// integrable somewhere in between.
+pub fn integrate<F: Function + ?Sized>(function: &mut F, a: f64, b: f64) -> f64 {
+    let mut quadrature = Quadrature { function, evaluations: MAX_EVALUATIONS };
+    let m = (a + b) / 2.0;
+    let (fa, fm, fb) = (quadrature.evaluate(a), quadrature.evaluate(m), quadrature.evaluate(b));
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    let tolerance = AREA_TOLERANCE * whole.abs().max(1.0);
+    quadrature.simpson((a, fa), (m, fm), (b, fb), whole, tolerance, MAX_DEPTH)
+}
+
+/// A function being integrated, with how many more times it may be evaluated.
+struct Quadrature<'a, F: ?Sized> {
+    function: &'a mut F,
+    evaluations: usize,
+}
+
+impl<F: Function + ?Sized> Quadrature<'_, F> {
+    fn evaluate(&mut self, x: f64) -> f64 {
+        self.evaluations = self.evaluations.saturating_sub(1);
+        self.function.evaluate(x)
+    }
+
+    fn simpson(&mut self, (a, fa): (f64, f64), (m, fm): (f64, f64), (b, fb): (f64, f64), whole: f64, tolerance: f64, depth: u32) -> f64 {
+        let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+        let (flm, frm) = (self.evaluate(lm), self.evaluate(rm));
+        let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+        let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+        let error = left + right - whole;
+
+        // Below a few ulps of the estimate the error is only rounding, and intervals this narrow
+        // can't be halved any further
+        let converged = error.abs() <= 15.0 * tolerance.max(4.0 * f64::EPSILON * (left + right).abs());
+        let exhausted = depth == 0 || self.evaluations == 0 || lm == a || lm == m || rm == m || rm == b;
+
+        if converged || exhausted || !error.is_finite() {
+            // Richardson extrapolation of the two estimates
+            return left + right + error / 15.0;
+        }
+
+        self.simpson((a, fa), (lm, flm), (m, fm), left, tolerance / 2.0, depth - 1)
+            + self.simpson((m, fm), (rm, frm), (b, fb), right, tolerance / 2.0, depth - 1)
+    }
+}
+
+/// Draws the tangent or normal line at `x` across the window, marking the point it touches.
+/// Returns `false` without drawing anything if the function has no value or slope at `x`.
+fn draw_line<F: Function + ?Sized, DB: DrawingBackend>(function: &mut F, x: f64, normal: bool, settings: &GraphSettings, color: &RGBColor, chart: &mut ChartOn<DB>) -> Result<bool, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    let y = function.evaluate(x);
+    let slope = function.slope(x, difference_step(settings));
+
+    if !y.is_finite() || !slope.is_finite() {
+        return Ok(false);
+    }
+
+    let line = match (normal, slope == 0.0) {
+        // The normal of a flat function is vertical
+        (true, true) => [(x, ymin), (x, ymax)],
+        (true, false) => [(xmin, y - (xmin - x) / slope), (xmax, y - (xmax - x) / slope)],
+        (false, _) => [(xmin, y + (xmin - x) * slope), (xmax, y + (xmax - x) * slope)],
+    };
+
+    chart.draw_series(LineSeries::new(line, color.stroke_width(2)))?;
+    chart.draw_series(std::iter::once(Circle::new((x, y), 4, color.filled())))?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integral_of_a_polynomial() {
+        let xs: Vec<f64> = (0..=40).map(|i| i as f64 * 0.1 - 2.0).collect();
+        let integral = running_integral(&mut |x: f64| 3.0 * x * x - 1.0, &xs, 0.55);
+
+        for (x, value) in integral {
+            let exact = (x * x * x - x) - (0.55_f64.powi(3) - 0.55);
+            assert!((value - exact).abs() < 1e-12, "{} instead of {} at {}", value, exact, x);
+        }
+    }
+
+    #[test]
+    fn integral_from_outside_the_samples() {
+        let xs: Vec<f64> = (0..=100).map(|i| i as f64 * 0.01).collect();
+        let integral = running_integral(&mut |x: f64| x.cos(), &xs, -3.0);
+
+        for (x, value) in integral {
+            let exact = x.sin() - (-3.0_f64).sin();
+            assert!((value - exact).abs() < 1e-9, "{} instead of {} at {}", value, exact, x);
+        }
+    }
+
+    #[test]
+    fn adaptive_quadrature() {
+        let area = integrate(&mut |x: f64| (-x * x).exp(), -6.0, 6.0);
+        assert!((area - std::f64::consts::PI.sqrt()).abs() < 1e-9);
+
+        // Flipping the bounds flips the sign
+        let area = integrate(&mut |x: f64| x.sin(), std::f64::consts::PI, 0.0);
+        assert!((area + 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn large_areas_use_a_relative_tolerance() {
+        let mut calls = 0;
+        let area = integrate(&mut |x: f64| { calls += 1; 1e8 * x.exp() }, 0.0, 1.0);
+        assert!((area / (1e8 * (std::f64::consts::E - 1.0)) - 1.0).abs() < 1e-9);
+        assert!(calls < 1000, "{} evaluations", calls);
+    }
+
+    #[test]
+    fn evaluations_are_capped() {
+        let mut calls = 0;
+        let area = integrate(&mut |x: f64| { calls += 1; (1.0 / x).sin() }, 1e-9, 1.0);
+        assert!(area.is_finite());
+        assert!(calls <= MAX_EVALUATIONS + 2 * MAX_DEPTH as usize, "{} evaluations", calls);
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!("-1:2.5".parse(), Ok(Span { start: -1.0, end: 2.5 }));
+        assert!("1".parse::<Span>().is_err());
+        assert!("1:b".parse::<Span>().is_err());
+    }
+
+    #[test]
+    fn integral_stops_where_undefined() {
+        let xs: Vec<f64> = (0..=20).map(|i| i as f64 * 0.1 - 1.0).collect();
+        let integral = running_integral(&mut |x: f64| x.sqrt(), &xs, 0.5);
+
+        assert!(integral.iter().filter(|(x, _)| *x > 0.05).all(|(_, value)| value.is_finite()));
+        assert!(integral.iter().filter(|(x, _)| *x < -0.05).all(|(_, value)| value.is_nan()));
+    }
+}
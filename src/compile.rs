@@ -0,0 +1,394 @@
+use crate::expr::{self, Expr, Func, Parsed, Relation};
+use crate::field::{Sample, ScalarField2D, ScalarField3D};
+
+/// One instruction, reading and writing registers by index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Const(usize, f64),
+    Load(usize, usize),
+    Neg(usize),
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Div(usize, usize),
+    Pow(usize, usize),
+    Powi(usize, i32),
+    Call(usize, Func),
+}
+
+/// An expression compiled to a flat list of register instructions, with the variables bound to
+/// slots by index instead of looked up by name on every evaluation. Subexpressions without
+/// variables are folded into constants.
+#[derive(Clone, Debug)]
+pub struct Program {
+    ops: Vec<Op>,
+    registers: Vec<f64>,
+    lanes: Vec<[f64; LANES]>,
+}
+
+/// How many points `eval_batch` evaluates together. Every instruction loops over a fixed size
+/// array of this many values, which the compiler turns into SIMD instructions.
+pub const LANES: usize = 8;
+
+/// The values of a variable slot in a batch, either one per point or the same for all of them.
+#[derive(Clone, Copy, Debug)]
+pub enum Input<'a> {
+    Varying(&'a [f64]),
+    Fixed(f64),
+}
+
+impl Program {
+    /// Compiles `expr` with `slots` naming the variables passed to `eval` in order. Any other
+    /// variable has to be one of the `constants`.
+    pub fn compile(expr: &Expr, slots: &[&str], constants: &[(&str, f64)]) -> Result<Program, String> {
+        let mut ops = Vec::new();
+        let registers = emit(&fold(expr, slots, constants)?, 0, slots, &mut ops);
+        Ok(Program { ops, registers: vec![0.0; registers], lanes: vec![[0.0; LANES]; registers] })
+    }
+
+    pub fn eval(&mut self, vars: &[f64]) -> f64 {
+        let r = &mut self.registers;
+        for op in &self.ops {
+            // Binary operations always combine a register with the one after it
+            match *op {
+                Op::Const(dst, val) => r[dst] = val,
+                Op::Load(dst, slot) => r[dst] = vars[slot],
+                Op::Neg(dst) => r[dst] = -r[dst],
+                Op::Add(dst, src) => r[dst] += r[src],
+                Op::Sub(dst, src) => r[dst] -= r[src],
+                Op::Mul(dst, src) => r[dst] *= r[src],
+                Op::Div(dst, src) => r[dst] /= r[src],
+                Op::Pow(dst, src) => r[dst] = r[dst].powf(r[src]),
+                Op::Powi(dst, n) => r[dst] = r[dst].powi(n),
+                Op::Call(dst, func) => r[dst] = func.apply(r[dst]),
+            }
+        }
+        r[0]
+    }
+
+    /// Evaluates every point of a batch, writing the results to `out`. Varying inputs need at
+    /// least as many values as `out` has room for.
+    pub fn eval_batch(&mut self, inputs: &[Input], out: &mut [f64]) {
+        for (chunk, out) in out.chunks_mut(LANES).enumerate() {
+            let start = chunk * LANES;
+            let r = &mut self.lanes;
+
+            for op in &self.ops {
+                match *op {
+                    Op::Const(dst, val) => r[dst] = [val; LANES],
+                    Op::Load(dst, slot) => match inputs[slot] {
+                        Input::Fixed(val) => r[dst] = [val; LANES],
+                        Input::Varying(values) => {
+                            // The last chunk may be short, the unused lanes just repeat a value
+                            let values = &values[start..start + out.len()];
+                            for (i, lane) in r[dst].iter_mut().enumerate() {
+                                *lane = values[i.min(values.len() - 1)];
+                            }
+                        }
+                    },
+                    Op::Neg(dst) => r[dst].iter_mut().for_each(|a| *a = -*a),
+                    Op::Add(dst, src) => lanewise(r, dst, src, |a, b| a + b),
+                    Op::Sub(dst, src) => lanewise(r, dst, src, |a, b| a - b),
+                    Op::Mul(dst, src) => lanewise(r, dst, src, |a, b| a * b),
+                    Op::Div(dst, src) => lanewise(r, dst, src, |a, b| a / b),
+                    Op::Pow(dst, src) => lanewise(r, dst, src, f64::powf),
+                    Op::Powi(dst, n) => r[dst].iter_mut().for_each(|a| *a = a.powi(n)),
+                    Op::Call(dst, func) => r[dst].iter_mut().for_each(|a| *a = func.apply(*a)),
+                }
+            }
+
+            out.copy_from_slice(&r[0][..out.len()]);
+        }
+    }
+}
+
+fn lanewise(r: &mut [[f64; LANES]], dst: usize, src: usize, f: impl Fn(f64, f64) -> f64) {
+    let src = r[src];
+    for (a, b) in r[dst].iter_mut().zip(src) {
+        *a = f(*a, b);
+    }
+}
+
+/// Replaces constants by their values and evaluates everything that doesn't depend on a slot.
+pub fn fold(expr: &Expr, slots: &[&str], constants: &[(&str, f64)]) -> Result<Expr, String> {
+    let unary = |a: &Expr, make: fn(Box<Expr>) -> Expr| -> Result<Expr, String> {
+        let a = fold(a, slots, constants)?;
+        Ok(match a {
+            Expr::Num(_) => Expr::Num(make(Box::new(a)).eval(&[])),
+            a => make(Box::new(a)),
+        })
+    };
+
+    let binary = |a: &Expr, b: &Expr, make: fn(Box<Expr>, Box<Expr>) -> Expr| -> Result<Expr, String> {
+        let (a, b) = (fold(a, slots, constants)?, fold(b, slots, constants)?);
+        Ok(match (a, b) {
+            (a @ Expr::Num(_), b @ Expr::Num(_)) => Expr::Num(make(Box::new(a), Box::new(b)).eval(&[])),
+            (a, b) => make(Box::new(a), Box::new(b)),
+        })
+    };
+
+    match expr {
+        Expr::Num(val) => Ok(Expr::Num(*val)),
+        Expr::Var(name) if slots.contains(&name.as_str()) => Ok(Expr::Var(name.clone())),
+        Expr::Var(name) => constants
+            .iter()
+            .find(|(constant, _)| *constant == name.as_str())
+            .map(|(_, val)| Expr::Num(*val))
+            .ok_or_else(|| format!("unknown variable '{}'", name)),
+        Expr::Neg(a) => unary(a, Expr::Neg),
+        Expr::Add(a, b) => binary(a, b, Expr::Add),
+        Expr::Sub(a, b) => binary(a, b, Expr::Sub),
+        Expr::Mul(a, b) => binary(a, b, Expr::Mul),
+        Expr::Div(a, b) => binary(a, b, Expr::Div),
+        Expr::Pow(a, b) => binary(a, b, Expr::Pow),
+        Expr::Call(func, a) => {
+            let a = fold(a, slots, constants)?;
+            Ok(match a {
+                Expr::Num(val) => Expr::Num(func.apply(val)),
+                a => Expr::Call(*func, Box::new(a)),
+            })
+        }
+    }
+}
+
+/// Emits instructions leaving the value of `expr` in register `dst`, using the registers after it
+/// as scratch space. Returns how many registers are needed in total.
+fn emit(expr: &Expr, dst: usize, slots: &[&str], ops: &mut Vec<Op>) -> usize {
+    let binary = |a: &Expr, b: &Expr, op: fn(usize, usize) -> Op, ops: &mut Vec<Op>| {
+        let used = emit(a, dst, slots, ops).max(emit(b, dst + 1, slots, ops));
+        ops.push(op(dst, dst + 1));
+        used
+    };
+
+    match expr {
+        Expr::Num(val) => {
+            ops.push(Op::Const(dst, *val));
+            dst + 1
+        }
+        Expr::Var(name) => {
+            // Folding has already replaced everything that isn't a slot
+            let slot = slots.iter().position(|slot| *slot == name.as_str()).unwrap_or_default();
+            ops.push(Op::Load(dst, slot));
+            dst + 1
+        }
+        Expr::Neg(a) => {
+            let used = emit(a, dst, slots, ops);
+            ops.push(Op::Neg(dst));
+            used
+        }
+        Expr::Pow(a, b) => match **b {
+            // Small integer powers like `x^2` are much cheaper with repeated multiplication
+            Expr::Num(n) if n.fract() == 0.0 && n.abs() <= 64.0 => {
+                let used = emit(a, dst, slots, ops);
+                ops.push(Op::Powi(dst, n as i32));
+                used
+            }
+            _ => binary(a, b, Op::Pow, ops),
+        },
+        Expr::Add(a, b) => binary(a, b, Op::Add, ops),
+        Expr::Sub(a, b) => binary(a, b, Op::Sub, ops),
+        Expr::Mul(a, b) => binary(a, b, Op::Mul, ops),
+        Expr::Div(a, b) => binary(a, b, Op::Div, ops),
+        Expr::Call(func, a) => {
+            let used = emit(a, dst, slots, ops);
+            ops.push(Op::Call(dst, *func));
+            used
+        }
+    }
+}
+
+/// The variables of a `CompiledField`, in the order they're passed to its programs.
+const SLOTS: [&str; 3] = ["x", "y", "z"];
+
+/// A compiled equation or inequality in `x`, `y` and `z`, evaluated as `lhs - rhs` like
+/// `BoundEquation` but without going through names.
+pub struct CompiledField {
+    program: Program,
+    /// `lhs - rhs` with the parameters folded in.
+    difference: Expr,
+    /// The exact partial derivatives of `difference` in x, y and z, only compiled once asked for
+    /// since just refining and shading need them.
+    gradient: Option<[Program; 3]>,
+    relation: Relation,
+    vars: [f64; 3],
+    row: Vec<f64>,
+}
+
+impl CompiledField {
+    pub fn new(parsed: &Parsed, params: &[(&str, f64)]) -> Result<CompiledField, String> {
+        let difference = fold(&parsed.difference(), &SLOTS, params)?;
+
+        Ok(CompiledField {
+            program: Program::compile(&difference, &SLOTS, &[])?,
+            difference,
+            gradient: None,
+            relation: parsed.relation,
+            vars: [0.0; 3],
+            row: Vec::new(),
+        })
+    }
+
+    /// Compiles an equation given as text, `None` if the `expr` parser rejects it or it uses
+    /// variables other than x, y, z and `params`.
+    pub fn parse(equation: &str, params: &[(&str, f64)]) -> Option<CompiledField> {
+        CompiledField::new(&expr::parse(equation).ok()?, params).ok()
+    }
+
+    fn gradient_at(&mut self, vars: [f64; 3]) -> [f64; 3] {
+        let difference = &self.difference;
+        let gradient = self.gradient.get_or_insert_with(|| {
+            SLOTS.map(|var| Program::compile(&difference.derivative(var), &SLOTS, &[]).expect("the parameters are folded in already"))
+        });
+        [gradient[0].eval(&vars), gradient[1].eval(&vars), gradient[2].eval(&vars)]
+    }
+
+    fn sample(&mut self) -> Sample {
+        to_sample(self.relation, self.program.eval(&self.vars))
+    }
+
+    fn sample_row(&mut self, inputs: [Input; 3], out: &mut [Sample]) {
+        self.row.resize(out.len(), 0.0);
+        self.program.eval_batch(&inputs, &mut self.row);
+        for (sample, difference) in out.iter_mut().zip(&self.row) {
+            *sample = to_sample(self.relation, *difference);
+        }
+    }
+}
+
+fn to_sample(relation: Relation, difference: f64) -> Sample {
+    match relation {
+        Relation::Equal => Sample::Value(difference).finite(),
+        _ if difference.is_nan() => Sample::Invalid,
+        Relation::Less => Sample::Inside(difference < 0.0),
+        Relation::LessEqual => Sample::Inside(difference <= 0.0),
+        Relation::Greater => Sample::Inside(difference > 0.0),
+        Relation::GreaterEqual => Sample::Inside(difference >= 0.0),
+    }
+}
+
+impl ScalarField2D for CompiledField {
+    fn evaluate(&mut self, x: f64, y: f64) -> Sample {
+        self.vars[0] = x;
+        self.vars[1] = y;
+        self.sample()
+    }
+
+    fn evaluate_row(&mut self, xs: &[f64], y: f64, out: &mut [Sample]) {
+        self.sample_row([Input::Varying(xs), Input::Fixed(y), Input::Fixed(0.0)], out);
+    }
+
+    fn gradient(&mut self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let [dx, dy, _] = self.gradient_at([x, y, 0.0]);
+        Some((dx, dy))
+    }
+}
+
+impl ScalarField3D for CompiledField {
+    fn evaluate(&mut self, x: f64, y: f64, z: f64) -> Sample {
+        self.vars = [x, y, z];
+        self.sample()
+    }
+
+    fn evaluate_row(&mut self, xs: &[f64], y: f64, z: f64, out: &mut [Sample]) {
+        self.sample_row([Input::Varying(xs), Input::Fixed(y), Input::Fixed(z)], out);
+    }
+
+    fn gradient(&mut self, x: f64, y: f64, z: f64) -> Option<(f64, f64, f64)> {
+        let [dx, dy, dz] = self.gradient_at([x, y, z]);
+        Some((dx, dy, dz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mathsolver::equation::Equation;
+
+    use super::*;
+
+    #[test]
+    fn matches_the_tree_walker() {
+        let equations = ["x^2+y^2=0.5", "sin(5x)=cos(3y)", "-x^3+2(y-1)/(x+0.1)=a", "sqrt(abs(x))^y=e^-x", "2^3^x=y*pi"];
+        let params = [("a", 0.75)];
+
+        for equation in equations {
+            let difference = expr::parse(equation).unwrap().difference();
+            let mut program = Program::compile(&difference, &["x", "y"], &params).unwrap();
+
+            for (x, y) in [(0.3, -0.7), (-1.2, 0.4), (2.0, 2.5), (0.0, 0.0)] {
+                let expected = difference.eval(&[("x", x), ("y", y), ("a", 0.75)]);
+                let actual = program.eval(&[x, y]);
+                assert!(
+                    actual == expected || (actual.is_nan() && expected.is_nan()) || (actual - expected).abs() <= 1e-12 * expected.abs(),
+                    "{} at ({}, {}): {} instead of {}", equation, x, y, actual, expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn batches_match_single_points() {
+        let difference = expr::parse("sin(5x)*y^2=ln(x)").unwrap().difference();
+        let mut program = Program::compile(&difference, &["x", "y"], &[]).unwrap();
+
+        // Not a multiple of the lane count, so the last chunk is short
+        let xs: Vec<f64> = (0..21).map(|i| i as f64 * 0.1 - 0.5).collect();
+        let mut out = vec![0.0; xs.len()];
+        program.eval_batch(&[Input::Varying(&xs), Input::Fixed(0.3)], &mut out);
+
+        for (x, actual) in xs.iter().zip(&out) {
+            let expected = program.eval(&[*x, 0.3]);
+            assert!(actual.to_bits() == expected.to_bits() || (actual.is_nan() && expected.is_nan()), "{} at x = {}", actual, x);
+        }
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let difference = expr::parse("x+b=y").unwrap().difference();
+        assert!(Program::compile(&difference, &["x", "y"], &[]).is_err());
+    }
+
+    #[test]
+    fn gradients_are_compiled_on_first_use() {
+        let mut field = CompiledField::parse("x^2+a*y*z=1", &[("a", 2.0)]).unwrap();
+        assert!(field.gradient.is_none());
+        assert_eq!(ScalarField3D::gradient(&mut field, 1.0, 2.0, 3.0), Some((2.0, 6.0, 4.0)));
+        assert!(field.gradient.is_some());
+    }
+
+    #[test]
+    fn matches_mathsolver() {
+        // Log base, implicit multiplication, coordinates run together, `e`, `1e-3` read as `1*e-3`,
+        // `^` associativity and unary minus
+        let equations = [
+            "log(x)+ln(y)=1",
+            "2(y+1)x=3sin(2x)",
+            "2xy=1",
+            "xy^2=y(x+1)",
+            "e^x=y",
+            "1e-3x=y",
+            "2^3^x=y",
+            "-x^2=y",
+            "-2^-x=y*-1",
+            "x^2+y^2<1",
+        ];
+
+        for equation in equations {
+            let mut compiled = CompiledField::parse(equation, &[]).unwrap();
+            let mut eq = Equation::new(equation);
+
+            for (x, y) in [(0.3, 0.7), (-1.2, 0.4), (2.1, 2.5), (0.5, -0.5)] {
+                let expected = ScalarField2D::evaluate(&mut eq, x, y);
+                let actual = ScalarField2D::evaluate(&mut compiled, x, y);
+                assert!(same_sample(actual, expected), "{} at ({}, {}): {:?} instead of {:?}", equation, x, y, actual, expected);
+            }
+        }
+    }
+
+    /// Whether two samples are equal, allowing values to differ by rounding.
+    fn same_sample(a: Sample, b: Sample) -> bool {
+        match (a, b) {
+            (Sample::Value(a), Sample::Value(b)) => (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0),
+            _ => a == b,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use plotters::prelude::*;
+
+use std::fs;
+use std::path::Path;
+
+use crate::ChartOn;
+use crate::error::GraphError;
+
+/// A measured point, optionally with the half-height of its error bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DataPoint {
+    pub x: f64,
+    pub y: f64,
+    pub error: Option<f64>,
+}
+
+/// Reads points from a CSV file with `x,y` or `x,y,error` columns. Lines starting with `#` are
+/// skipped, and so is a header on the first line that isn't one.
+pub fn load_points(path: &Path) -> Result<Vec<DataPoint>, GraphError> {
+    let text = fs::read_to_string(path).map_err(|e| GraphError::file(path, e))?;
+    parse_points(&text).map_err(|e| GraphError::BadArgument(format!("{}:{}", path.display(), e)))
+}
+
+/// Parses the contents of a points file, see `load_points`. Errors start with the line number.
+fn parse_points(text: &str) -> Result<Vec<DataPoint>, String> {
+    let mut points = Vec::new();
+    let mut first = true;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let columns: Result<Vec<f64>, _> = line.split(',').map(|v| v.trim().parse::<f64>()).collect();
+        let header = std::mem::replace(&mut first, false);
+
+        let columns = match columns {
+            Ok(columns) => columns,
+            Err(_) if header => continue,
+            Err(e) => return Err(format!("{}: {}", number + 1, e)),
+        };
+
+        match columns[..] {
+            [x, y] => points.push(DataPoint { x, y, error: None }),
+            [x, y, error] => points.push(DataPoint { x, y, error: Some(error.abs()) }),
+            _ => return Err(format!("{}: expected x,y or x,y,error", number + 1)),
+        }
+    }
+
+    Ok(points)
+}
+
+/// Draws the points as markers with their error bars, optionally connecting them in order.
+pub fn draw_points<DB: DrawingBackend>(points: &[DataPoint], connect: bool, color: &RGBColor, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    if connect {
+        chart.draw_series(LineSeries::new(points.iter().map(|p| (p.x, p.y)), color.stroke_width(1)))?;
+    }
+
+    chart.draw_series(
+        points
+            .iter()
+            .filter_map(|p| p.error.map(|error| ErrorBar::new_vertical(p.x, p.y - error, p.y, p.y + error, color.stroke_width(1), 6))),
+    )?;
+
+    chart.draw_series(points.iter().map(|p| Circle::new((p.x, p.y), 3, color.filled())))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_points() {
+        let points = parse_points("0, 1\n\n2, 3, -0.5\n").unwrap();
+        assert_eq!(points, [DataPoint { x: 0.0, y: 1.0, error: None }, DataPoint { x: 2.0, y: 3.0, error: Some(0.5) }]);
+    }
+
+    #[test]
+    fn skips_a_header_after_comments() {
+        let points = parse_points("# measured on the 3rd\n\n# in metres\nx, y\n1, 2\n").unwrap();
+        assert_eq!(points, [DataPoint { x: 1.0, y: 2.0, error: None }]);
+    }
+
+    #[test]
+    fn only_skips_one_header() {
+        assert_eq!(parse_points("x, y\n1, 2\nx, y\n").err().unwrap(), "3: invalid float literal");
+        assert_eq!(parse_points("x, y\nvolts, amps\n").err().unwrap(), "2: invalid float literal");
+    }
+
+    #[test]
+    fn rejects_other_column_counts() {
+        assert_eq!(parse_points("1, 2, 3, 4\n").err().unwrap(), "1: expected x,y or x,y,error");
+        assert_eq!(parse_points("1\n").err().unwrap(), "1: expected x,y or x,y,error");
+    }
+}
@@ -0,0 +1,165 @@
+use std::f64::consts::LN_10;
+
+use crate::expr::{Expr, Func};
+
+impl Expr {
+    /// The exact derivative with respect to `var`, with the trivial simplifications applied so
+    /// that the result stays small enough to compile and evaluate quickly.
+    pub fn derivative(&self, var: &str) -> Expr {
+        match self {
+            Expr::Num(_) => Expr::Num(0.0),
+            Expr::Var(name) => Expr::Num(if name == var { 1.0 } else { 0.0 }),
+            Expr::Neg(a) => neg(a.derivative(var)),
+            Expr::Add(a, b) => add(a.derivative(var), b.derivative(var)),
+            Expr::Sub(a, b) => sub(a.derivative(var), b.derivative(var)),
+            Expr::Mul(a, b) => add(mul(a.derivative(var), (**b).clone()), mul((**a).clone(), b.derivative(var))),
+            Expr::Div(a, b) => div(
+                sub(mul(a.derivative(var), (**b).clone()), mul((**a).clone(), b.derivative(var))),
+                pow((**b).clone(), Expr::Num(2.0)),
+            ),
+            Expr::Pow(a, b) => {
+                let (da, db) = (a.derivative(var), b.derivative(var));
+                match (&da, &db) {
+                    (_, Expr::Num(n)) if *n == 0.0 => {
+                        // a^n with a constant exponent
+                        mul(mul((**b).clone(), pow((**a).clone(), sub((**b).clone(), Expr::Num(1.0)))), da)
+                    }
+                    (Expr::Num(n), _) if *n == 0.0 => {
+                        // n^b with a constant base
+                        mul(mul(self.clone(), call(Func::Ln, (**a).clone())), db)
+                    }
+                    _ => mul(
+                        self.clone(),
+                        add(mul(db, call(Func::Ln, (**a).clone())), div(mul((**b).clone(), da), (**a).clone())),
+                    ),
+                }
+            }
+            Expr::Call(func, a) => {
+                let a = (**a).clone();
+                let outer = match func {
+                    Func::Sin => call(Func::Cos, a.clone()),
+                    Func::Cos => neg(call(Func::Sin, a.clone())),
+                    Func::Tan => div(Expr::Num(1.0), pow(call(Func::Cos, a.clone()), Expr::Num(2.0))),
+                    Func::Asin => div(Expr::Num(1.0), call(Func::Sqrt, sub(Expr::Num(1.0), pow(a.clone(), Expr::Num(2.0))))),
+                    Func::Acos => div(Expr::Num(-1.0), call(Func::Sqrt, sub(Expr::Num(1.0), pow(a.clone(), Expr::Num(2.0))))),
+                    Func::Atan => div(Expr::Num(1.0), add(Expr::Num(1.0), pow(a.clone(), Expr::Num(2.0)))),
+                    Func::Sinh => call(Func::Cosh, a.clone()),
+                    Func::Cosh => call(Func::Sinh, a.clone()),
+                    Func::Tanh => div(Expr::Num(1.0), pow(call(Func::Cosh, a.clone()), Expr::Num(2.0))),
+                    Func::Sqrt => div(Expr::Num(0.5), call(Func::Sqrt, a.clone())),
+                    Func::Exp => call(Func::Exp, a.clone()),
+                    Func::Ln => div(Expr::Num(1.0), a.clone()),
+                    Func::Log => div(Expr::Num(1.0), mul(a.clone(), Expr::Num(LN_10))),
+                    // The sign of the argument, undefined at zero like the derivative itself
+                    Func::Abs => div(a.clone(), call(Func::Abs, a.clone())),
+                };
+                mul(outer, a.derivative(var))
+            }
+        }
+    }
+}
+
+fn num(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Num(val) => Some(*val),
+        _ => None,
+    }
+}
+
+fn neg(a: Expr) -> Expr {
+    match a {
+        Expr::Num(val) => Expr::Num(-val),
+        Expr::Neg(a) => *a,
+        a => Expr::Neg(Box::new(a)),
+    }
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    match (num(&a), num(&b)) {
+        (Some(x), Some(y)) => Expr::Num(x + y),
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        _ => Expr::Add(Box::new(a), Box::new(b)),
+    }
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    match (num(&a), num(&b)) {
+        (Some(x), Some(y)) => Expr::Num(x - y),
+        (Some(0.0), _) => neg(b),
+        (_, Some(0.0)) => a,
+        _ => Expr::Sub(Box::new(a), Box::new(b)),
+    }
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    match (num(&a), num(&b)) {
+        (Some(x), Some(y)) => Expr::Num(x * y),
+        (Some(0.0), _) | (_, Some(0.0)) => Expr::Num(0.0),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        (Some(-1.0), _) => neg(b),
+        (_, Some(-1.0)) => neg(a),
+        _ => Expr::Mul(Box::new(a), Box::new(b)),
+    }
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    match (num(&a), num(&b)) {
+        (Some(x), Some(y)) => Expr::Num(x / y),
+        (Some(0.0), _) => Expr::Num(0.0),
+        (_, Some(1.0)) => a,
+        _ => Expr::Div(Box::new(a), Box::new(b)),
+    }
+}
+
+fn pow(a: Expr, b: Expr) -> Expr {
+    match (num(&a), num(&b)) {
+        (Some(x), Some(y)) => Expr::Num(x.powf(y)),
+        (_, Some(0.0)) => Expr::Num(1.0),
+        (_, Some(1.0)) => a,
+        _ => Expr::Pow(Box::new(a), Box::new(b)),
+    }
+}
+
+fn call(func: Func, a: Expr) -> Expr {
+    match a {
+        Expr::Num(val) => Expr::Num(func.apply(val)),
+        a => Expr::Call(func, Box::new(a)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::parse;
+
+    #[test]
+    fn matches_finite_differences() {
+        let equations = [
+            "x^3-2x", "sin(x)*cos(2x)", "tan(x)/x", "exp(-x^2)", "ln(x^2+1)", "sqrt(x+2)", "2^x", "x^x",
+            "asin(x/2)", "acos(x/2)", "atan(3x)", "sinh(x)+cosh(x)*tanh(x)", "log(x+3)", "abs(x-0.1)",
+        ];
+
+        for equation in equations {
+            let f = parse(equation).unwrap().difference();
+            let df = f.derivative("x");
+
+            for x in [-0.7, 0.3, 0.9, 1.4] {
+                let h = 1e-6;
+                let numeric = (f.eval(&[("x", x + h)]) - f.eval(&[("x", x - h)])) / (2.0 * h);
+                let exact = df.eval(&[("x", x)]);
+                if numeric.is_finite() {
+                    assert!((exact - numeric).abs() <= 1e-5 * numeric.abs().max(1.0), "d/dx {} at {}: {} instead of {}", equation, x, exact, numeric);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn partial_derivatives() {
+        let f = parse("x^2*y+sin(y)").unwrap().difference();
+        let vars = [("x", 0.5), ("y", 2.0)];
+        assert!((f.derivative("x").eval(&vars) - 2.0).abs() < 1e-12);
+        assert!((f.derivative("y").eval(&vars) - (0.25 + 2.0_f64.cos())).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,115 @@
+use plotters::prelude::*;
+use plotters_backend::DrawingErrorKind;
+use plotters_bitmap::BitMapBackendError;
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::expr::ParseError;
+
+/// Everything that can go wrong while graphing. Every kind has its own exit code so that scripts
+/// wrapping the command line tool can tell a bad equation apart from a full disk.
+#[derive(Debug)]
+pub enum GraphError {
+    /// A command line value, option combination or input file that doesn't make sense.
+    BadArgument(String),
+    /// An equation that could not be parsed, with the byte position of the problem.
+    Parse { equation: String, position: usize, message: String },
+    /// An equation that parsed but could not be evaluated the way it was used.
+    Evaluation(String),
+    /// Drawing the graph failed.
+    Render(String),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+}
+
+impl GraphError {
+    pub fn parse(equation: &str, error: ParseError) -> GraphError {
+        GraphError::Parse { equation: equation.to_string(), position: error.position, message: error.message }
+    }
+
+    /// An IO error with the path of the file it happened to, which `io::Error` leaves out.
+    pub fn file(path: &Path, e: io::Error) -> GraphError {
+        GraphError::Io(io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    /// The exit code the command line tool ends with for this error. `2` matches what clap uses
+    /// for arguments it rejects itself.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GraphError::BadArgument(_) => 2,
+            GraphError::Parse { .. } => 3,
+            GraphError::Evaluation(_) => 4,
+            GraphError::Render(_) => 5,
+            GraphError::Io(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::BadArgument(message) => write!(f, "{}", message),
+            GraphError::Parse { equation, position, message } => {
+                writeln!(f, "could not parse the equation: {}", message)?;
+                writeln!(f, "  {}", equation)?;
+                // Point at the character, counting characters rather than bytes
+                write!(f, "  {}^", " ".repeat(equation[..*position].chars().count()))
+            }
+            GraphError::Evaluation(message) => write!(f, "could not evaluate the equation: {}", message),
+            GraphError::Render(message) => write!(f, "could not draw the graph: {}", message),
+            GraphError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for GraphError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GraphError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GraphError {
+    fn from(e: io::Error) -> Self {
+        GraphError::Io(e)
+    }
+}
+
+impl From<fmt::Error> for GraphError {
+    fn from(e: fmt::Error) -> Self {
+        GraphError::Render(e.to_string())
+    }
+}
+
+impl From<BitMapBackendError> for GraphError {
+    fn from(e: BitMapBackendError) -> Self {
+        match e {
+            BitMapBackendError::IOError(e) => GraphError::Io(e),
+            e => GraphError::Render(e.to_string()),
+        }
+    }
+}
+
+impl<E: Error + Send + Sync + 'static> From<DrawingAreaErrorKind<E>> for GraphError {
+    /// Backends report failing to write the image as one of their own errors, those are pulled
+    /// back out as `Io` so that a full disk isn't reported as a drawing problem.
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        if let DrawingAreaErrorKind::BackendError(DrawingErrorKind::DrawingError(backend)) = &e {
+            let backend = backend as &dyn Any;
+            if let Some(BitMapBackendError::IOError(io)) = backend.downcast_ref::<BitMapBackendError>() {
+                return GraphError::Io(io::Error::new(io.kind(), io.to_string()));
+            }
+            if let Some(io) = backend.downcast_ref::<io::Error>() {
+                return GraphError::Io(io::Error::new(io.kind(), io.to_string()));
+            }
+        }
+
+        GraphError::Render(e.to_string())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::compile::{fold, Program};
+use crate::expr::{self, Expr, Relation};
+use crate::field::Function;
+use crate::error::GraphError;
+
+/// A function `y = f(x)`, drawn by evaluating it along x instead of contouring `f(x) - y`.
+#[derive(Clone, Debug)]
+pub struct Explicit {
+    /// `f` with the parameters folded in, so that x is the only variable left.
+    expr: Expr,
+    program: Program,
+    slope: Program,
+}
+
+impl Explicit {
+    /// Reads `f(x)`, `y = f(x)` or `f(x) = y`. Any other use of y, or a relation other than `=`,
+    /// is rejected since the graph would then not be a function of x.
+    pub fn parse(equation: &str, params: &[(&str, f64)]) -> Result<Explicit, GraphError> {
+        let parsed = expr::parse(equation).map_err(|e| GraphError::parse(equation, e))?;
+        let not_explicit = || GraphError::BadArgument(format!("'{}' is not of the form y = f(x)", equation));
+
+        let y = Expr::Var("y".to_string());
+        let f = if !equation.contains(&['=', '<', '>'][..]) {
+            parsed.lhs
+        } else if parsed.relation != Relation::Equal {
+            return Err(not_explicit());
+        } else if parsed.lhs == y {
+            parsed.rhs
+        } else if parsed.rhs == y {
+            parsed.lhs
+        } else {
+            return Err(not_explicit());
+        };
+
+        let mut variables = Vec::new();
+        f.variables(&mut variables);
+        if variables.iter().any(|name| name == "y") {
+            return Err(not_explicit());
+        }
+
+        let expr = fold(&f, &["x"], params).map_err(|e| GraphError::Evaluation(format!("{} in '{}'", e, equation)))?;
+        Ok(Explicit::from_folded(expr))
+    }
+
+    fn from_folded(expr: Expr) -> Explicit {
+        let compile = |expr: &Expr| Program::compile(expr, &["x"], &[]).expect("x is the only variable left after folding");
+        Explicit { program: compile(&expr), slope: compile(&expr.derivative("x")), expr }
+    }
+
+    pub fn eval(&mut self, x: f64) -> f64 {
+        self.program.eval(&[x])
+    }
+
+    /// The exact derivative `f'(x)`.
+    pub fn derivative(&self) -> Explicit {
+        Explicit::from_folded(self.expr.derivative("x"))
+    }
+}
+
+impl Function for Explicit {
+    fn evaluate(&mut self, x: f64) -> f64 {
+        self.eval(x)
+    }
+
+    fn slope(&mut self, x: f64, _h: f64) -> f64 {
+        self.slope.eval(&[x])
+    }
+}
+
+/// Parses a function of x for drawing alongside others of any kind.
+pub fn parse_function(equation: &str, params: &[(&str, f64)]) -> Result<Box<dyn Function>, GraphError> {
+    Ok(Box::new(Explicit::parse(equation, params)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_forms_give_the_same_function() {
+        for equation in ["x^2-a", "y=x^2-a", "x^2-a=y"] {
+            let mut f = Explicit::parse(equation, &[("a", 1.0)]).unwrap();
+            assert_eq!(f.eval(3.0), 8.0, "{}", equation);
+        }
+    }
+
+    #[test]
+    fn implicit_equations_are_rejected() {
+        for equation in ["x^2+y^2=1", "y=x*y", "y<x", "sin(x)=0"] {
+            assert!(matches!(Explicit::parse(equation, &[]), Err(GraphError::BadArgument(_))), "{}", equation);
+        }
+    }
+
+    #[test]
+    fn derivative() {
+        let mut df = Explicit::parse("y=x^3+sin(x)", &[]).unwrap().derivative();
+        assert!((df.eval(0.5) - (0.75 + 0.5_f64.cos())).abs() < 1e-12);
+        assert!((df.slope(0.5, 0.0) - (3.0 - 0.5_f64.sin())).abs() < 1e-12);
+    }
+}
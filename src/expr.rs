@@ -0,0 +1,425 @@
+use std::error::Error;
+use std::f64::consts::{E, PI};
+use std::fmt;
+
+/// An expression tree parsed from the same syntax `mathsolver` accepts, for the analyses that need
+/// to look inside an equation rather than just evaluate it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(Func, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sqrt,
+    Exp,
+    Ln,
+    Log,
+    Abs,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        Some(match name {
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "asin" | "arcsin" => Func::Asin,
+            "acos" | "arccos" => Func::Acos,
+            "atan" | "arctan" => Func::Atan,
+            "sinh" => Func::Sinh,
+            "cosh" => Func::Cosh,
+            "tanh" => Func::Tanh,
+            "sqrt" => Func::Sqrt,
+            "exp" => Func::Exp,
+            "ln" => Func::Ln,
+            "log" => Func::Log,
+            "abs" => Func::Abs,
+            _ => return None,
+        })
+    }
+
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            Func::Sin => x.sin(),
+            Func::Cos => x.cos(),
+            Func::Tan => x.tan(),
+            Func::Asin => x.asin(),
+            Func::Acos => x.acos(),
+            Func::Atan => x.atan(),
+            Func::Sinh => x.sinh(),
+            Func::Cosh => x.cosh(),
+            Func::Tanh => x.tanh(),
+            Func::Sqrt => x.sqrt(),
+            Func::Exp => x.exp(),
+            Func::Ln => x.ln(),
+            Func::Log => x.log10(),
+            Func::Abs => x.abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Equal,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// An equation or inequality `lhs <relation> rhs`. Expressions without a relation are read as
+/// `expr = 0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parsed {
+    pub lhs: Expr,
+    pub relation: Relation,
+    pub rhs: Expr,
+}
+
+impl Parsed {
+    /// `lhs - rhs`, which is zero on the curve and negative where `lhs < rhs`.
+    pub fn difference(&self) -> Expr {
+        Expr::Sub(Box::new(self.lhs.clone()), Box::new(self.rhs.clone()))
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at position {}: {}", self.position, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Expr {
+    pub fn eval(&self, vars: &[(&str, f64)]) -> f64 {
+        match self {
+            Expr::Num(val) => *val,
+            Expr::Var(name) => vars.iter().find(|(var, _)| *var == name.as_str()).map_or(f64::NAN, |(_, val)| *val),
+            Expr::Neg(a) => -a.eval(vars),
+            Expr::Add(a, b) => a.eval(vars) + b.eval(vars),
+            Expr::Sub(a, b) => a.eval(vars) - b.eval(vars),
+            Expr::Mul(a, b) => a.eval(vars) * b.eval(vars),
+            Expr::Div(a, b) => a.eval(vars) / b.eval(vars),
+            Expr::Pow(a, b) => a.eval(vars).powf(b.eval(vars)),
+            Expr::Call(func, a) => func.apply(a.eval(vars)),
+        }
+    }
+
+    /// Collects the names of all variables used, without duplicates.
+    pub fn variables(&self, out: &mut Vec<String>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(name) => {
+                if !out.contains(name) {
+                    out.push(name.clone());
+                }
+            }
+            Expr::Neg(a) | Expr::Call(_, a) => a.variables(out),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Pow(a, b) => {
+                a.variables(out);
+                b.variables(out);
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Parsed, ParseError> {
+    let mut parser = Parser { text, chars: text.char_indices().collect(), pos: 0 };
+
+    let lhs = parser.expr()?;
+    parser.skip_whitespace();
+
+    let relation = match (parser.peek(), parser.peek_at(1)) {
+        (None, _) => return Ok(Parsed { lhs, relation: Relation::Equal, rhs: Expr::Num(0.0) }),
+        (Some('<'), Some('=')) => Relation::LessEqual,
+        (Some('>'), Some('=')) => Relation::GreaterEqual,
+        (Some('='), _) => Relation::Equal,
+        (Some('<'), _) => Relation::Less,
+        (Some('>'), _) => Relation::Greater,
+        (Some(c), _) => return Err(parser.error(format!("unexpected '{}'", c))),
+    };
+    parser.pos += if matches!(relation, Relation::LessEqual | Relation::GreaterEqual) { 2 } else { 1 };
+
+    let rhs = parser.expr()?;
+    parser.skip_whitespace();
+
+    match parser.peek() {
+        None => Ok(Parsed { lhs, relation, rhs }),
+        Some(c) => Err(parser.error(format!("unexpected '{}'", c))),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Vec<(usize, char)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).map(|(_, c)| *c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn byte_pos(&self) -> usize {
+        self.chars.get(self.pos).map_or(self.text.len(), |(i, _)| *i)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError { position: self.byte_pos(), message }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found the end of the equation", expected))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    lhs = Expr::Add(Box::new(lhs), Box::new(self.term()?));
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    lhs = Expr::Sub(Box::new(lhs), Box::new(self.term()?));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    lhs = Expr::Mul(Box::new(lhs), Box::new(self.unary()?));
+                }
+                Some('/') => {
+                    self.pos += 1;
+                    lhs = Expr::Div(Box::new(lhs), Box::new(self.unary()?));
+                }
+                // Implicit multiplication, as in `2x` or `3(x+1)`
+                Some(c) if c.is_alphanumeric() || c == '(' || c == '.' => {
+                    lhs = Expr::Mul(Box::new(lhs), Box::new(self.power()?));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.primary()?;
+        self.skip_whitespace();
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            // Right associative, so that `2^3^2` is `2^(3^2)`
+            return Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => self.identifier(),
+            Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of the equation".to_string())),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().map(|(_, c)| c).collect();
+        text.parse()
+            .map(Expr::Num)
+            .map_err(|_| ParseError { position: self.chars[start].0, message: format!("invalid number '{}'", text) })
+    }
+
+    fn identifier(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().map(|(_, c)| c).collect();
+
+        // Runs of coordinates like `xy` are products, as in `mathsolver`, so only the first one is
+        // read here and implicit multiplication picks up the rest. Other names are one variable.
+        if name.chars().all(|c| matches!(c, 'x' | 'y' | 'z')) {
+            self.pos = start + 1;
+            return Ok(Expr::Var(name[..1].to_string()));
+        }
+
+        self.skip_whitespace();
+        if self.peek() == Some('(') {
+            let func = Func::from_name(&name).ok_or_else(|| ParseError {
+                position: self.chars[start].0,
+                message: format!("unknown function '{}'", name),
+            })?;
+            self.pos += 1;
+            let arg = self.expr()?;
+            self.expect(')')?;
+            return Ok(Expr::Call(func, Box::new(arg)));
+        }
+
+        Ok(match name.as_str() {
+            "pi" => Expr::Num(PI),
+            "e" => Expr::Num(E),
+            _ => Expr::Var(name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, x: f64) -> f64 {
+        parse(text).unwrap().lhs.eval(&[("x", x)])
+    }
+
+    fn error(text: &str) -> (usize, String) {
+        let e = parse(text).unwrap_err();
+        (e.position, e.message)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1+2*3^2", 0.0), 19.0);
+        assert_eq!(eval("8/2/2", 0.0), 2.0);
+        assert_eq!(eval("1-2-3", 0.0), -4.0);
+        assert_eq!(eval("(1+2)*3", 0.0), 9.0);
+        // Implicit multiplication binds like `*`
+        assert_eq!(eval("1/2x", 4.0), 2.0);
+        assert_eq!(eval("2(x+1)3", 1.0), 12.0);
+        assert_eq!(eval("2x^2", 3.0), 18.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-x^2", 3.0), -9.0);
+        assert_eq!(eval("-2^2", 0.0), -4.0);
+        assert_eq!(eval("2^-1", 0.0), 0.5);
+        assert_eq!(eval("x*-2", 3.0), -6.0);
+        assert_eq!(eval("--x", 3.0), 3.0);
+        assert_eq!(eval("+x", 3.0), 3.0);
+    }
+
+    #[test]
+    fn powers_are_right_associative() {
+        assert_eq!(eval("2^3^2", 0.0), 512.0);
+        assert_eq!(eval("(2^3)^2", 0.0), 64.0);
+        assert_eq!(eval("2^-1^2", 0.0), 0.5);
+    }
+
+    #[test]
+    fn relations() {
+        let relation = |text| parse(text).unwrap().relation;
+        assert_eq!(relation("x<=y"), Relation::LessEqual);
+        assert_eq!(relation("x >= y"), Relation::GreaterEqual);
+        assert_eq!(relation("x<y"), Relation::Less);
+        assert_eq!(relation("x>y"), Relation::Greater);
+        assert_eq!(parse("x^2-1").unwrap(), Parsed { lhs: parse("x^2-1=0").unwrap().lhs, relation: Relation::Equal, rhs: Expr::Num(0.0) });
+    }
+
+    #[test]
+    fn identifiers() {
+        let variables = |text| {
+            let mut out = Vec::new();
+            parse(text).unwrap().difference().variables(&mut out);
+            out
+        };
+        assert_eq!(variables("freq*x=a_1+b2"), ["freq", "x", "a_1", "b2"]);
+        assert_eq!(variables("sin (x)+pi*e=y"), ["x", "y"]);
+        assert_eq!(variables("2yzx=a"), ["y", "z", "x", "a"]);
+        // Coordinates run together multiply, with powers binding to the last one only
+        assert_eq!(eval("2xx^2", 3.0), 54.0);
+        assert_eq!(eval("xx(x+1)", 2.0), 12.0);
+        assert_eq!(parse("arcsin(x)").unwrap().lhs, Expr::Call(Func::Asin, Box::new(Expr::Var("x".to_string()))));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("x^2+=y"), (4, "unexpected '='".to_string()));
+        assert_eq!(error("sin(x=y"), (5, "expected ')', found '='".to_string()));
+        assert_eq!(error("(x+y"), (4, "expected ')', found the end of the equation".to_string()));
+        assert_eq!(error("x=y)"), (3, "unexpected ')'".to_string()));
+        assert_eq!(error("2*foo(x)=y"), (2, "unknown function 'foo'".to_string()));
+        assert_eq!(error("x=1.2.3"), (2, "invalid number '1.2.3'".to_string()));
+        assert_eq!(error("x="), (2, "unexpected end of the equation".to_string()));
+        // Positions are in bytes, `θ` takes two
+        assert_eq!(error("θ+=y").0, 3);
+    }
+}
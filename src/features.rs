@@ -0,0 +1,290 @@
+use plotters::prelude::*;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::{ChartOn, GraphSettings};
+use crate::calculus::difference_step;
+use crate::field::Function;
+use crate::plot::sample_xs;
+use crate::error::GraphError;
+
+/// How often a bracket is halved to find a zero, which reaches the precision of `f64`.
+const BISECTIONS: usize = 64;
+
+/// The step of the differences taken of the derivative for the second derivative, as a fraction
+/// of the window. Larger than `difference_step` since numeric derivatives are already noisy.
+const CURVATURE_STEP: f64 = 1e-4;
+
+/// How close to zero the smallest value of a function between two samples has to come, relative
+/// to the largest sampled value, to count as a root that touches zero without crossing it.
+const TOUCH_TOLERANCE: f64 = 1e-9;
+
+/// How many times the rounding error of the function the second derivative may be off by, which
+/// sign changes of the second derivative have to exceed to count as inflection points.
+const CURVATURE_NOISE: f64 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Root,
+    Minimum,
+    Maximum,
+    Inflection,
+}
+
+impl Kind {
+    fn color(self) -> RGBColor {
+        match self {
+            Kind::Root => RED,
+            Kind::Minimum => BLUE,
+            Kind::Maximum => GREEN,
+            Kind::Inflection => MAGENTA,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Kind::Root => "root",
+            Kind::Minimum => "minimum",
+            Kind::Maximum => "maximum",
+            Kind::Inflection => "inflection",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Feature {
+    pub kind: Kind,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// The roots, local extrema and inflection points of a function within the window, ordered by x.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Features {
+    pub points: Vec<Feature>,
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<12} {:>14} {:>14}", "point", "x", "y")?;
+        for point in &self.points {
+            write!(f, "\n{:<12} {:>14.6} {:>14.6}", point.kind, point.x, point.y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the points where the function, its derivative and its second derivative change sign
+/// between the samples `plot_x` takes, and narrows each down by bisection.
+pub fn find_features<F: Function + ?Sized>(function: &mut F, settings: &GraphSettings) -> Features {
+    let xs = sample_xs(settings);
+    let h = difference_step(settings);
+    let h2 = (settings.sim_window.1 - settings.sim_window.0) * CURVATURE_STEP;
+
+    // Differences of differences amplify rounding, enough for the second derivative of a line to
+    // change sign at random when the slope isn't exact
+    let scale = xs.iter().map(|&x| function.evaluate(x).abs()).filter(|y| y.is_finite()).fold(0.0, f64::max);
+    let noise = CURVATURE_NOISE * f64::EPSILON * scale / (h * h2);
+
+    let mut found = Vec::new();
+    for (x, _) in zeros(&mut |x| function.evaluate(x), &xs, true, 0.0) {
+        found.push((Kind::Root, x));
+    }
+    for (x, rising) in zeros(&mut |x| function.slope(x, h), &xs, false, 0.0) {
+        found.push((if rising { Kind::Minimum } else { Kind::Maximum }, x));
+    }
+    for (x, _) in zeros(&mut |x| (function.slope(x + h2, h) - function.slope(x - h2, h)) / (2.0 * h2), &xs, false, noise) {
+        found.push((Kind::Inflection, x));
+    }
+
+    let mut points: Vec<_> = found
+        .into_iter()
+        .map(|(kind, x)| Feature { kind, x, y: if kind == Kind::Root { 0.0 } else { function.evaluate(x) } })
+        .filter(|point| point.y.is_finite())
+        .collect();
+    points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+
+    Features { points }
+}
+
+/// Marks the points on the chart, labelled with their kind.
+pub fn mark_features<DB: DrawingBackend>(features: &Features, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    chart.draw_series(features.points.iter().map(|point| {
+        EmptyElement::at((point.x, point.y))
+            + Circle::new((0, 0), 4, point.kind.color().filled())
+            + Text::new(point.kind.to_string(), (6, 4), ("sans-serif", 13).into_font())
+    }))?;
+
+    Ok(())
+}
+
+/// The zeros of `g` between samples at `xs` where it changes sign, with whether it rises through
+/// zero there. With `touching`, zeros where `g` only touches zero count too, like the double root
+/// of `x^2`, as do samples at either end where `g` is exactly zero. Sign changes between values
+/// both smaller than `noise` are taken to be rounding and skipped.
+pub fn zeros<G: FnMut(f64) -> f64>(g: &mut G, xs: &[f64], touching: bool, noise: f64) -> Vec<(f64, bool)> {
+    let values: Vec<f64> = xs.iter().map(|&x| g(x)).collect();
+    let scale = values.iter().map(|value| value.abs()).filter(|value| value.is_finite()).fold(0.0, f64::max);
+    let mut zeros = Vec::new();
+
+    for (i, window) in values.windows(3).enumerate() {
+        let (before, value, after) = (window[0], window[1], window[2]);
+        // A stretch of zeros, like the second derivative of a line, has no single point to mark
+        if value == 0.0 && before != 0.0 && after != 0.0 && (touching || before * after < 0.0) && before.abs().max(after.abs()) >= noise {
+            zeros.push((xs[i + 1], after > 0.0));
+        }
+
+        // Between samples an even-order root is only a minimum of |g| that comes close to zero. A
+        // minimum between two equal samples is seen from both, so ties only count on the right
+        if touching && before * value > 0.0 && value * after > 0.0 && value.abs() < before.abs() && value.abs() <= after.abs() {
+            let x = minimize_abs(g, xs[i], xs[i + 2]);
+            if g(x).abs() <= TOUCH_TOLERANCE * scale {
+                zeros.push((x, after > 0.0));
+            }
+        }
+    }
+
+    // Roots on the first or last sample have no neighbour on the far side
+    let n = values.len();
+    if touching && n >= 2 {
+        if values[0] == 0.0 && values[1] != 0.0 {
+            zeros.push((xs[0], values[1] > 0.0));
+        }
+        if values[n - 1] == 0.0 && values[n - 2] != 0.0 {
+            zeros.push((xs[n - 1], values[n - 2] < 0.0));
+        }
+    }
+
+    for (i, window) in values.windows(2).enumerate() {
+        let (a, b) = (window[0], window[1]);
+        if a * b < 0.0 && a.abs().max(b.abs()) >= noise {
+            let x = bisect(g, xs[i], xs[i + 1], a);
+            // Near a pole the sign changes too, but the value grows instead of vanishing
+            if g(x).abs() <= a.abs().min(b.abs()) {
+                zeros.push((x, a < 0.0));
+            }
+        }
+    }
+
+    zeros
+}
+
+/// Narrows down where `|g|` is smallest between `a` and `b` by golden-section search, assuming it
+/// only has one minimum there.
+fn minimize_abs<G: FnMut(f64) -> f64>(g: &mut G, mut a: f64, mut b: f64) -> f64 {
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let (mut c, mut d) = (b - ratio * (b - a), a + ratio * (b - a));
+    let (mut gc, mut gd) = (g(c).abs(), g(d).abs());
+
+    for _ in 0..BISECTIONS {
+        if gc < gd {
+            b = d;
+            d = c;
+            gd = gc;
+            c = b - ratio * (b - a);
+            gc = g(c).abs();
+        } else {
+            a = c;
+            c = d;
+            gc = gd;
+            d = a + ratio * (b - a);
+            gd = g(d).abs();
+        }
+    }
+    (a + b) / 2.0
+}
+
+/// Narrows down a sign change of `g` between `a` and `b`, where `g` is `ga` at `a`.
+fn bisect<G: FnMut(f64) -> f64>(g: &mut G, mut a: f64, mut b: f64, mut ga: f64) -> f64 {
+    for _ in 0..BISECTIONS {
+        let m = (a + b) / 2.0;
+        let gm = g(m);
+        if gm == 0.0 {
+            return m;
+        }
+        if (gm < 0.0) == (ga < 0.0) {
+            a = m;
+            ga = gm;
+        } else {
+            b = m;
+        }
+    }
+    (a + b) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explicit::Explicit;
+
+    fn settings(sim_window: (f64, f64, f64, f64)) -> GraphSettings<'static> {
+        GraphSettings { path: "", image_width: 64, image_height: 64, sim_window, yaw: 0.5, params: &[], refine: None }
+    }
+
+    #[test]
+    fn cubic() {
+        let mut f = Explicit::parse("y=x^3-x", &[]).unwrap();
+        let features = find_features(&mut f, &settings((-2.0, 2.0, -2.0, 2.0)));
+
+        let extremum = 1.0 / 3.0_f64.sqrt();
+        let expected = [
+            (Kind::Root, -1.0),
+            (Kind::Maximum, -extremum),
+            (Kind::Root, 0.0),
+            (Kind::Inflection, 0.0),
+            (Kind::Minimum, extremum),
+            (Kind::Root, 1.0),
+        ];
+
+        assert_eq!(features.points.len(), expected.len(), "{}", features);
+        for (point, (kind, x)) in features.points.iter().zip(expected) {
+            assert_eq!(point.kind, kind);
+            assert!((point.x - x).abs() < 1e-9, "{} at {} instead of {}", kind, point.x, x);
+        }
+    }
+
+    #[test]
+    fn double_root() {
+        // 0.3 lies between samples, so the function never reaches zero at one
+        let features = find_features(&mut |x: f64| (x - 0.3).powi(2), &settings((-1.0, 1.0, -1.0, 1.0)));
+
+        assert_eq!(features.points.len(), 2, "{}", features);
+        for kind in [Kind::Root, Kind::Minimum] {
+            assert!(features.points.iter().any(|point| point.kind == kind && (point.x - 0.3).abs() < 1e-6), "no {} in\n{}", kind, features);
+        }
+    }
+
+    #[test]
+    fn near_misses_are_not_roots() {
+        let features = find_features(&mut |x: f64| (x - 0.3).powi(2) + 1e-3, &settings((-1.0, 1.0, -1.0, 1.0)));
+        let kinds: Vec<_> = features.points.iter().map(|point| point.kind).collect();
+        assert_eq!(kinds, [Kind::Minimum]);
+    }
+
+    #[test]
+    fn roots_at_the_edges() {
+        let features = find_features(&mut |x: f64| x * x - 1.0, &settings((-1.0, 1.0, -1.0, 1.0)));
+        let roots: Vec<_> = features.points.iter().filter(|point| point.kind == Kind::Root).map(|point| point.x).collect();
+        assert_eq!(roots, [-1.0, 1.0]);
+    }
+
+    #[test]
+    fn lines_have_no_inflection_points() {
+        // The slope of a closure is a central difference, so the second derivative is all rounding
+        let features = find_features(&mut |x: f64| 0.1 * x + 0.2, &settings((-2.0, 2.0, -2.0, 2.0)));
+        let kinds: Vec<_> = features.points.iter().map(|point| point.kind).collect();
+        assert_eq!(kinds, [Kind::Root]);
+    }
+
+    #[test]
+    fn poles_are_not_features() {
+        let features = find_features(&mut |x: f64| 1.0 / x, &settings((-1.0, 1.0, -1.0, 1.0)));
+        assert!(features.points.is_empty(), "{}", features);
+    }
+}
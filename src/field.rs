@@ -0,0 +1,158 @@
+use mathsolver::equation::{Equation, Node};
+
+use crate::SubEqual;
+use crate::compile::CompiledField;
+use crate::expr;
+use crate::error::GraphError;
+
+/// What a field evaluates to at a single point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sample {
+    /// A signed value, the plotted curve or surface is where it crosses zero.
+    Value(f64),
+    /// Whether the point lies in the region described by an inequality.
+    Inside(bool),
+    /// The field has no usable value here, like `ln(x)` for negative `x` or `1/x` at zero.
+    Invalid,
+}
+
+impl Sample {
+    fn from_node(node: Node) -> Sample {
+        match node {
+            Node::Bool(val) => Sample::Inside(val),
+            Node::Real(val) => Sample::Value(val),
+            _ => Sample::Invalid,
+        }
+        .finite()
+    }
+
+    /// Turns `NaN` and infinite values into `Invalid`.
+    pub fn finite(self) -> Sample {
+        match self {
+            Sample::Value(val) if !val.is_finite() => Sample::Invalid,
+            sample => sample,
+        }
+    }
+}
+
+pub trait ScalarField2D {
+    fn evaluate(&mut self, x: f64, y: f64) -> Sample;
+
+    /// Evaluates a whole row of points sharing `y`, for fields that can do that faster than one
+    /// point at a time.
+    fn evaluate_row(&mut self, xs: &[f64], y: f64, out: &mut [Sample]) {
+        for (sample, x) in out.iter_mut().zip(xs) {
+            *sample = self.evaluate(*x, y);
+        }
+    }
+
+    /// The exact gradient at a point, for fields that know it.
+    fn gradient(&mut self, _x: f64, _y: f64) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+pub trait ScalarField3D {
+    fn evaluate(&mut self, x: f64, y: f64, z: f64) -> Sample;
+
+    /// Evaluates a whole row of points sharing `y` and `z`, for fields that can do that faster
+    /// than one point at a time.
+    fn evaluate_row(&mut self, xs: &[f64], y: f64, z: f64, out: &mut [Sample]) {
+        for (sample, x) in out.iter_mut().zip(xs) {
+            *sample = self.evaluate(*x, y, z);
+        }
+    }
+
+    /// The exact gradient at a point, for fields that know it.
+    fn gradient(&mut self, _x: f64, _y: f64, _z: f64) -> Option<(f64, f64, f64)> {
+        None
+    }
+}
+
+/// A function `y = f(x)`, as drawn by `plot_x`.
+pub trait Function {
+    /// `NaN` where the function isn't defined.
+    fn evaluate(&mut self, x: f64) -> f64;
+
+    /// The derivative at `x`, by central differences with step `h` unless the function knows it
+    /// exactly.
+    fn slope(&mut self, x: f64, h: f64) -> f64 {
+        (self.evaluate(x + h) - self.evaluate(x - h)) / (2.0 * h)
+    }
+}
+
+impl<F: FnMut(f64) -> f64> Function for F {
+    fn evaluate(&mut self, x: f64) -> f64 {
+        self(x)
+    }
+}
+
+impl<F: FnMut(f64, f64) -> f64> ScalarField2D for F {
+    fn evaluate(&mut self, x: f64, y: f64) -> Sample {
+        Sample::Value(self(x, y)).finite()
+    }
+}
+
+impl<F: FnMut(f64, f64, f64) -> f64> ScalarField3D for F {
+    fn evaluate(&mut self, x: f64, y: f64, z: f64) -> Sample {
+        Sample::Value(self(x, y, z)).finite()
+    }
+}
+
+impl ScalarField2D for Equation {
+    fn evaluate(&mut self, x: f64, y: f64) -> Sample {
+        Sample::from_node(self.call_on_custom::<SubEqual>(&[("x", x), ("y", y)]))
+    }
+}
+
+impl ScalarField3D for Equation {
+    fn evaluate(&mut self, x: f64, y: f64, z: f64) -> Sample {
+        Sample::from_node(self.call_on_custom::<SubEqual>(&[("x", x), ("y", y), ("z", z)]))
+    }
+}
+
+/// An equation with its free variables bound to fixed values, evaluated as `lhs - rhs`.
+pub struct BoundEquation<'a, 'b> {
+    eq: &'a mut Equation,
+    bindings: Vec<(&'b str, f64)>,
+}
+
+impl<'a, 'b> BoundEquation<'a, 'b> {
+    pub fn new(eq: &'a mut Equation, params: &[(&'b str, f64)]) -> BoundEquation<'a, 'b> {
+        BoundEquation {
+            eq,
+            bindings: [&[("x", 0.0), ("y", 0.0), ("z", 0.0)][..], params].concat(),
+        }
+    }
+}
+
+impl ScalarField2D for BoundEquation<'_, '_> {
+    fn evaluate(&mut self, x: f64, y: f64) -> Sample {
+        self.bindings[0].1 = x;
+        self.bindings[1].1 = y;
+        Sample::from_node(self.eq.call_on_custom::<SubEqual>(&self.bindings))
+    }
+}
+
+impl ScalarField3D for BoundEquation<'_, '_> {
+    fn evaluate(&mut self, x: f64, y: f64, z: f64) -> Sample {
+        self.bindings[0].1 = x;
+        self.bindings[1].1 = y;
+        self.bindings[2].1 = z;
+        Sample::from_node(self.eq.call_on_custom::<SubEqual>(&self.bindings))
+    }
+}
+
+/// Compiles an equation into a field, reporting syntax the `expr` parser doesn't understand as a
+/// `GraphError::Parse`.
+pub fn parse_field(equation: &str, params: &[(&str, f64)]) -> Result<CompiledField, GraphError> {
+    let parsed = expr::parse(equation).map_err(|e| GraphError::parse(equation, e))?;
+    CompiledField::new(&parsed, params).map_err(|e| GraphError::Evaluation(format!("{} in '{}'", e, equation)))
+}
+
+/// Parses an equation for the code that evaluates it with `mathsolver` directly, checking it with
+/// the `expr` parser first since `mathsolver` panics on some malformed equations.
+pub fn parse_equation(equation: &str) -> Result<Equation, GraphError> {
+    expr::parse(equation).map_err(|e| GraphError::parse(equation, e))?;
+    Ok(Equation::new(equation))
+}
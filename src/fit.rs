@@ -0,0 +1,266 @@
+use mathsolver::equation::Equation;
+
+use std::fmt;
+
+use crate::data::DataPoint;
+use crate::field::{BoundEquation, Sample, ScalarField2D};
+use crate::error::GraphError;
+
+const MAX_ITERATIONS: usize = 200;
+const TOLERANCE: f64 = 1e-12;
+
+/// The result of fitting the free parameters of an equation to data.
+pub struct Fit {
+    pub params: Vec<(String, f64)>,
+    /// The standard error of each parameter, `NaN` when there are no degrees of freedom left.
+    pub std_errors: Vec<f64>,
+    pub iterations: usize,
+    /// The sum of squared residuals.
+    pub ssr: f64,
+    pub rms: f64,
+    /// The coefficient of determination, `NaN` when every point has the same y.
+    pub r_squared: f64,
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<12} {:>16} {:>16}", "parameter", "value", "std. error")?;
+        for ((name, value), error) in self.params.iter().zip(&self.std_errors) {
+            writeln!(f, "{:<12} {:>16.8} {:>16.8}", name, value, error)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "iterations   {}", self.iterations)?;
+        writeln!(f, "SSR          {:.8}", self.ssr)?;
+        writeln!(f, "RMS          {:.8}", self.rms)?;
+        write!(f, "R²           {:.8}", self.r_squared)
+    }
+}
+
+/// Parses initial guesses given as `a=1,b=0.5`.
+pub fn parse_guesses(s: &str) -> Result<Vec<(String, f64)>, String> {
+    s.split(',')
+        .map(|guess| {
+            let (name, value) = guess.split_once('=').ok_or_else(|| format!("expected NAME=VALUE, got '{}'", guess))?;
+            let value = value.trim().parse().map_err(|e| format!("invalid value for {}: {}", name.trim(), e))?;
+            Ok((name.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// Fits the parameters of `eq` to the points with Levenberg-Marquardt, minimising the sum of
+/// squares of `lhs - rhs` evaluated at every point. For equations like `y=a*exp(b*x)` that is the
+/// ordinary vertical least squares fit.
+pub fn fit(eq: &mut Equation, points: &[DataPoint], initial: &[(String, f64)]) -> Result<Fit, GraphError> {
+    let m = points.len();
+    let n = initial.len();
+
+    if m < n {
+        return Err(GraphError::BadArgument(format!("{} points are not enough to fit {} parameters", m, n)));
+    }
+
+    let mut params: Vec<f64> = initial.iter().map(|(_, value)| *value).collect();
+    let mut residuals = evaluate_residuals(eq, points, initial, &params)?;
+    let mut cost = sum_of_squares(&residuals);
+    let mut lambda = 1e-3;
+    let mut iterations = 0;
+
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+
+        let jacobian = jacobian(eq, points, initial, &params, &residuals)?;
+        let (jtj, jtr) = normal_equations(&jacobian, &residuals, n);
+
+        let mut improved = false;
+        while lambda < 1e12 {
+            let mut damped = jtj.clone();
+            for i in 0..n {
+                damped[i][i] += lambda * jtj[i][i].max(1e-12);
+            }
+
+            let step = match solve(damped, jtr.iter().map(|v| -v).collect()) {
+                Some(step) => step,
+                None => {
+                    lambda *= 10.0;
+                    continue;
+                }
+            };
+
+            let candidate: Vec<f64> = params.iter().zip(&step).map(|(p, s)| p + s).collect();
+            let candidate_residuals = evaluate_residuals(eq, points, initial, &candidate)?;
+            let candidate_cost = sum_of_squares(&candidate_residuals);
+
+            if candidate_cost.is_finite() && candidate_cost <= cost {
+                let converged = (cost - candidate_cost) <= TOLERANCE * cost.max(TOLERANCE)
+                    && step.iter().zip(&candidate).all(|(s, p)| s.abs() <= 1e-10 * (p.abs() + 1e-10));
+                params = candidate;
+                residuals = candidate_residuals;
+                cost = candidate_cost;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = !converged;
+                break;
+            }
+
+            lambda *= 10.0;
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    let jacobian = jacobian(eq, points, initial, &params, &residuals)?;
+    let (jtj, _) = normal_equations(&jacobian, &residuals, n);
+    let variance = if m > n { cost / (m - n) as f64 } else { f64::NAN };
+    let std_errors = (0..n)
+        .map(|i| {
+            let mut unit = vec![0.0; n];
+            unit[i] = 1.0;
+            solve(jtj.clone(), unit).map_or(f64::NAN, |column| (column[i] * variance).sqrt())
+        })
+        .collect();
+
+    let mean = points.iter().map(|p| p.y).sum::<f64>() / m as f64;
+    let total = points.iter().map(|p| (p.y - mean).powi(2)).sum::<f64>();
+
+    Ok(Fit {
+        params: initial.iter().zip(params).map(|((name, _), value)| (name.clone(), value)).collect(),
+        std_errors,
+        iterations,
+        ssr: cost,
+        rms: (cost / m as f64).sqrt(),
+        r_squared: if total > 0.0 { 1.0 - cost / total } else { f64::NAN },
+    })
+}
+
+fn evaluate_residuals(eq: &mut Equation, points: &[DataPoint], names: &[(String, f64)], params: &[f64]) -> Result<Vec<f64>, GraphError> {
+    let bindings: Vec<_> = names.iter().zip(params).map(|((name, _), value)| (name.as_str(), *value)).collect();
+    let mut field = BoundEquation::new(eq, &bindings);
+
+    points
+        .iter()
+        .map(|p| -> Result<f64, GraphError> {
+            match field.evaluate(p.x, p.y) {
+                Sample::Value(val) => Ok(val),
+                Sample::Inside(_) => Err(GraphError::Evaluation("only equations can be fitted, not inequalities".to_string())),
+                Sample::Invalid => Err(GraphError::Evaluation(format!("no value at ({}, {})", p.x, p.y))),
+            }
+        })
+        .collect()
+}
+
+/// Forward difference approximation of the derivatives of the residuals with respect to each
+/// parameter, stored as one row per point.
+fn jacobian(eq: &mut Equation, points: &[DataPoint], names: &[(String, f64)], params: &[f64], residuals_at: &[f64]) -> Result<Vec<Vec<f64>>, GraphError> {
+    let mut jacobian = vec![vec![0.0; params.len()]; points.len()];
+
+    for j in 0..params.len() {
+        let h = f64::EPSILON.sqrt() * params[j].abs().max(1.0);
+        let mut shifted = params.to_vec();
+        shifted[j] += h;
+
+        for (row, (r, r0)) in jacobian.iter_mut().zip(evaluate_residuals(eq, points, names, &shifted)?.iter().zip(residuals_at)) {
+            row[j] = (r - r0) / h;
+        }
+    }
+
+    Ok(jacobian)
+}
+
+fn normal_equations(jacobian: &[Vec<f64>], residuals: &[f64], n: usize) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let mut jtj = vec![vec![0.0; n]; n];
+    let mut jtr = vec![0.0; n];
+
+    for (row, r) in jacobian.iter().zip(residuals) {
+        for i in 0..n {
+            jtr[i] += row[i] * r;
+            for j in 0..n {
+                jtj[i][j] += row[i] * row[j];
+            }
+        }
+    }
+
+    (jtj, jtr)
+}
+
+fn sum_of_squares(values: &[f64]) -> f64 {
+    values.iter().map(|v| v * v).sum()
+}
+
+/// Solves `a * x = b` with Gaussian elimination and partial pivoting, `None` if `a` is singular.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 || !a[pivot][col].is_finite() {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (above, below) = a.split_at_mut(col + 1);
+        let pivot_row = &above[col];
+        for (offset, target) in below.iter_mut().enumerate() {
+            let factor = target[col] / pivot_row[col];
+            for (value, pivot) in target[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(f: impl Fn(f64) -> f64) -> Vec<DataPoint> {
+        (0..20).map(|i| i as f64 * 0.25).map(|x| DataPoint { x, y: f(x), error: None }).collect()
+    }
+
+    fn guesses(guesses: &[(&str, f64)]) -> Vec<(String, f64)> {
+        guesses.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    #[test]
+    fn recovers_known_parameters() {
+        let points = points(|x| 2.0 * (0.5 * x).exp());
+        let fit = fit(&mut Equation::new("y=a*exp(b*x)"), &points, &guesses(&[("a", 1.0), ("b", 0.1)])).unwrap();
+
+        assert!((fit.params[0].1 - 2.0).abs() < 1e-6, "a = {}", fit.params[0].1);
+        assert!((fit.params[1].1 - 0.5).abs() < 1e-6, "b = {}", fit.params[1].1);
+        assert!(fit.ssr < 1e-12);
+        assert!((fit.r_squared - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn constant_data_has_no_r_squared() {
+        let fit = fit(&mut Equation::new("y=a"), &points(|_| 3.0), &guesses(&[("a", 0.0)])).unwrap();
+        assert!((fit.params[0].1 - 3.0).abs() < 1e-9);
+        assert!(fit.r_squared.is_nan());
+    }
+
+    #[test]
+    fn needs_as_many_points_as_parameters() {
+        let points = &points(|x| x)[..1];
+        assert!(matches!(fit(&mut Equation::new("y=a*x+b"), points, &guesses(&[("a", 1.0), ("b", 0.0)])), Err(GraphError::BadArgument(_))));
+    }
+
+    #[test]
+    fn solves_linear_systems() {
+        let x = solve(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]).unwrap();
+        assert!((x[0] - 0.8).abs() < 1e-12 && (x[1] - 1.4).abs() < 1e-12, "{:?}", x);
+
+        // The second row is a multiple of the first
+        assert_eq!(solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]), None);
+        assert_eq!(solve(vec![vec![0.0, 0.0], vec![0.0, 0.0]], vec![0.0, 0.0]), None);
+    }
+}
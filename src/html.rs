@@ -0,0 +1,103 @@
+use mathsolver::equation::Equation;
+use plotters::prelude::*;
+
+use std::fmt::Write;
+use std::fs;
+
+use crate::{GraphSettings, create_graph};
+use crate::animate::Sweep;
+use crate::plot::plot;
+use crate::error::GraphError;
+
+const STYLE: &str = "
+body { font-family: sans-serif; }
+.frame { display: none; }
+.frame.shown { display: block; }
+";
+
+const SCRIPT: &str = "
+const frames = document.querySelectorAll('.frame');
+const slider = document.getElementById('slider');
+const label = document.getElementById('label');
+function show(i) {
+    frames.forEach((frame, j) => frame.classList.toggle('shown', i === j));
+    label.textContent = label.dataset.name + ' = ' + frames[i].dataset.value;
+}
+slider.addEventListener('input', () => show(Number(slider.value)));
+show(0);
+";
+
+/// Renders every value of the sweep to an SVG and writes them, together with a slider to flip
+/// between them, into a single HTML page at `settings.path`.
+pub fn slider_html(eqs: &mut [Equation], settings: &GraphSettings, sweep: &Sweep) -> Result<(), GraphError> {
+    let decimals = decimals(sweep);
+    let mut frames = String::new();
+    let mut frame_count = 0;
+
+    for value in sweep.values() {
+        let params = [(sweep.name.as_str(), value)];
+        let frame_settings = GraphSettings {
+            params: &params,
+            ..*settings
+        };
+
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, (settings.image_width, settings.image_height)).into_drawing_area();
+            root.fill(&WHITE)?;
+            let mut graph = create_graph(&frame_settings, &root)?;
+            for eq in eqs.iter_mut() {
+                plot(eq, &frame_settings, &mut graph)?;
+            }
+            root.present()?;
+        }
+
+        writeln!(frames, "<div class=\"frame\" data-value=\"{:.*}\">{}</div>", decimals, value, svg)?;
+        frame_count += 1;
+    }
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>\n<meta charset=\"utf-8\">\n<title>Graph</title>\n<style>{}</style>\n</head>", STYLE)?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "{}", frames)?;
+    writeln!(html, "<input id=\"slider\" type=\"range\" min=\"0\" max=\"{}\" step=\"1\" value=\"0\">", frame_count - 1)?;
+    writeln!(html, "<span id=\"label\" data-name=\"{}\"></span>", sweep.name)?;
+    writeln!(html, "<script>{}</script>", SCRIPT)?;
+    writeln!(html, "</body>\n</html>")?;
+
+    fs::write(settings.path, html)?;
+
+    Ok(())
+}
+
+/// The number of decimals that shows every value of a sweep exactly, taken from the shortest
+/// representations of its start and step rather than from adding up steps, which leaves rounding noise.
+fn decimals(sweep: &Sweep) -> usize {
+    let digits = |value: f64| value.to_string().split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    digits(sweep.start).max(digits(sweep.step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(sweep: &str) -> Vec<String> {
+        let sweep: Sweep = sweep.parse().unwrap();
+        let decimals = decimals(&sweep);
+        sweep.values().map(|value| format!("{:.*}", decimals, value)).collect()
+    }
+
+    #[test]
+    fn labels_show_every_digit_of_the_step() {
+        assert_eq!(labels("a=0:1:0.25"), ["0.00", "0.25", "0.50", "0.75", "1.00"]);
+        assert_eq!(labels("a=0:3:1"), ["0", "1", "2", "3"]);
+        assert_eq!(labels("a=0:0.3:0.1"), ["0.0", "0.1", "0.2", "0.3"]);
+    }
+
+    #[test]
+    fn labels_show_every_digit_of_the_start() {
+        assert_eq!(labels("a=0.5:2.5:1"), ["0.5", "1.5", "2.5"]);
+    }
+}
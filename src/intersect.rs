@@ -0,0 +1,192 @@
+use plotters::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::{ChartOn, GraphSettings};
+use crate::features::zeros;
+use crate::field::{Function, ScalarField2D};
+use crate::plot::{grid_size, sample_xs, to_window};
+use crate::refine::{newton_system, DEFAULT_TOLERANCE};
+use crate::report::{Intersection, Report};
+use crate::error::GraphError;
+
+/// A plotted equation with its contour lines, in the `-1.0..=1.0` square.
+pub type Contour<'a> = (&'a str, Box<dyn ScalarField2D + 'a>, Vec<[(f64, f64); 2]>);
+
+/// Finds where the contours of every pair of equations cross, marks the points with their
+/// coordinates and adds them to `report`.
+pub fn mark_intersections<DB: DrawingBackend>(contours: &mut [Contour<'_>], settings: &GraphSettings, chart: &mut ChartOn<DB>, report: &mut Report) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let start = report.intersections.len();
+
+    for i in 0..contours.len() {
+        let (head, tail) = contours.split_at_mut(i + 1);
+        let (name_a, a, lines_a) = &mut head[i];
+        for (name_b, b, lines_b) in tail.iter_mut() {
+            for (x, y) in intersections(a.as_mut(), lines_a, b.as_mut(), lines_b, settings) {
+                report.intersections.push(Intersection { a: name_a.to_string(), b: name_b.to_string(), x, y });
+            }
+        }
+    }
+
+    draw_intersections(&report.intersections[start..], chart)
+}
+
+/// Finds where the graphs of every pair of functions of x cross, marks the points with their
+/// coordinates and adds them to `report`.
+pub fn mark_function_intersections<DB: DrawingBackend>(functions: &mut [(&str, Box<dyn Function + '_>)], settings: &GraphSettings, chart: &mut ChartOn<DB>, report: &mut Report) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let start = report.intersections.len();
+
+    for i in 0..functions.len() {
+        let (head, tail) = functions.split_at_mut(i + 1);
+        let (name_a, a) = &mut head[i];
+        for (name_b, b) in tail.iter_mut() {
+            for (x, y) in function_intersections(a.as_mut(), b.as_mut(), settings) {
+                report.intersections.push(Intersection { a: name_a.to_string(), b: name_b.to_string(), x, y });
+            }
+        }
+    }
+
+    draw_intersections(&report.intersections[start..], chart)
+}
+
+fn draw_intersections<DB: DrawingBackend>(intersections: &[Intersection], chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    chart.draw_series(intersections.iter().map(|point| {
+        EmptyElement::at((point.x, point.y))
+            + Circle::new((0, 0), 4, RED.filled())
+            + Text::new(format!("({:.3}, {:.3})", point.x, point.y), (6, -16), ("sans-serif", 13).into_font())
+    }))?;
+
+    Ok(())
+}
+
+/// The points where the graphs of `a` and `b` cross or touch, the zeros of `a - b` between the
+/// samples `plot_x` takes.
+pub fn function_intersections<A: Function + ?Sized, B: Function + ?Sized>(a: &mut A, b: &mut B, settings: &GraphSettings) -> Vec<(f64, f64)> {
+    zeros(&mut |x| a.evaluate(x) - b.evaluate(x), &sample_xs(settings), true, 0.0)
+        .into_iter()
+        .map(|(x, _)| (x, a.evaluate(x)))
+        .collect()
+}
+
+/// The points where the contour lines of `a` and `b` cross, found between the segments and then
+/// moved onto both curves with Newton steps, in the coordinates of the window.
+pub fn intersections<A: ScalarField2D + ?Sized, B: ScalarField2D + ?Sized>(a: &mut A, lines_a: &[[(f64, f64); 2]], b: &mut B, lines_b: &[[(f64, f64); 2]], settings: &GraphSettings) -> Vec<(f64, f64)> {
+    let (width, height) = grid_size(settings);
+    let cell = (2.0 / (width - 1) as f64, 2.0 / (height - 1) as f64);
+    let cell_of = |(x, y): (f64, f64)| (((x + 1.0) / cell.0).floor() as i64, ((y + 1.0) / cell.1).floor() as i64);
+
+    // Every segment lies within one grid cell, so only segments in the same or neighbouring
+    // cells can cross
+    let mut buckets: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, [p, q]) in lines_b.iter().enumerate() {
+        buckets.entry(cell_of(midpoint(*p, *q))).or_default().push(i);
+    }
+
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    let diagonal = ((xmax - xmin) * cell.0 / 2.0).hypot((ymax - ymin) * cell.1 / 2.0);
+    let tolerance = settings.refine.unwrap_or(DEFAULT_TOLERANCE);
+
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for segment in lines_a {
+        let (cx, cy) = cell_of(midpoint(segment[0], segment[1]));
+        let nearby = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (cx + dx, cy + dy)));
+
+        for i in nearby.filter_map(|key| buckets.get(&key)).flatten() {
+            let crossing = match crossing(*segment, lines_b[*i]) {
+                Some(crossing) => to_window(settings, crossing),
+                None => continue,
+            };
+            // Curves that touch without crossing keep the point where the segments meet
+            let point = newton_system(a, b, crossing, tolerance, diagonal).unwrap_or(crossing);
+
+            // Crossings at a shared vertex are found in both segments next to it
+            if points.iter().all(|other| (other.0 - point.0).hypot(other.1 - point.1) > diagonal / 2.0) {
+                points.push(point);
+            }
+        }
+    }
+
+    points
+}
+
+fn midpoint(p: (f64, f64), q: (f64, f64)) -> (f64, f64) {
+    ((p.0 + q.0) / 2.0, (p.1 + q.1) / 2.0)
+}
+
+/// Where two segments cross, `None` if they don't or are parallel.
+fn crossing([p, p2]: [(f64, f64); 2], [q, q2]: [(f64, f64); 2]) -> Option<(f64, f64)> {
+    let r = (p2.0 - p.0, p2.1 - p.1);
+    let s = (q2.0 - q.0, q2.1 - q.1);
+    let denominator = r.0 * s.1 - r.1 * s.0;
+    if denominator == 0.0 {
+        return None;
+    }
+
+    let d = (q.0 - p.0, q.1 - p.1);
+    let t = (d.0 * s.1 - d.1 * s.0) / denominator;
+    let u = (d.0 * r.1 - d.1 * r.0) / denominator;
+
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some((p.0 + t * r.0, p.1 + t * r.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::CompiledField;
+    use crate::plot::sample_2d;
+    use crate::marching_squares;
+
+    fn contour<F: ScalarField2D>(field: &mut F, settings: &GraphSettings) -> Vec<[(f64, f64); 2]> {
+        let grid = sample_2d(field, settings);
+        marching_squares(grid.width, grid.height, grid.values)
+    }
+
+    #[test]
+    fn segments_crossing() {
+        assert_eq!(crossing([(0.0, 0.0), (1.0, 1.0)], [(0.0, 1.0), (1.0, 0.0)]), Some((0.5, 0.5)));
+        assert_eq!(crossing([(0.0, 0.0), (1.0, 1.0)], [(0.0, 1.0), (1.0, 2.0)]), None);
+        assert_eq!(crossing([(0.0, 0.0), (1.0, 0.0)], [(2.0, -1.0), (2.0, 1.0)]), None);
+    }
+
+    #[test]
+    fn circle_and_line() {
+        let settings = GraphSettings { path: "", image_width: 20, image_height: 20, sim_window: (-1.0, 1.0, -1.0, 1.0), yaw: 0.5, params: &[], refine: None };
+        let mut circle = CompiledField::parse("x^2+y^2=0.5", &[]).unwrap();
+        let mut line = |x: f64, y: f64| y - x - 0.1;
+        let (lines_circle, lines_line) = (contour(&mut circle, &settings), contour(&mut line, &settings));
+
+        let mut points = intersections(&mut circle, &lines_circle, &mut line, &lines_line, &settings);
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        // Where x^2 + (x + 0.1)^2 = 0.5
+        let root = (0.99_f64).sqrt() / 2.0;
+        let expected = [(-0.05 - root, 0.05 - root), (-0.05 + root, 0.05 + root)];
+        assert_eq!(points.len(), 2);
+        for (point, expected) in points.iter().zip(&expected) {
+            assert!((point.0 - expected.0).abs() < 1e-9 && (point.1 - expected.1).abs() < 1e-9, "{:?} instead of {:?}", point, expected);
+        }
+    }
+
+    #[test]
+    fn parabola_and_line() {
+        let settings = GraphSettings { path: "", image_width: 64, image_height: 64, sim_window: (-2.0, 2.0, -2.0, 2.0), yaw: 0.5, params: &[], refine: None };
+        let points = function_intersections(&mut |x: f64| x * x, &mut |x: f64| x + 0.5, &settings);
+
+        // Where x^2 = x + 0.5
+        let root = 3.0_f64.sqrt() / 2.0;
+        let expected = [(0.5 - root, 1.0 - root), (0.5 + root, 1.0 + root)];
+        assert_eq!(points.len(), 2);
+        for (point, expected) in points.iter().zip(&expected) {
+            assert!((point.0 - expected.0).abs() < 1e-9 && (point.1 - expected.1).abs() < 1e-9, "{:?} instead of {:?}", point, expected);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use plotters::prelude::*;
+
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::{GraphSettings, ChartOn};
+use crate::expr::{Expr, Func, Parsed, Relation};
+use crate::error::GraphError;
+
+/// A closed interval of reals, with `lo > hi` meaning empty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+    /// Set when part of the input fell outside the domain of an operation or hit a pole, so the
+    /// function isn't known to be defined and continuous over the whole interval.
+    pub partial: bool,
+}
+
+impl Interval {
+    pub const EMPTY: Interval = Interval { lo: f64::INFINITY, hi: f64::NEG_INFINITY, partial: true };
+    pub const ENTIRE: Interval = Interval { lo: f64::NEG_INFINITY, hi: f64::INFINITY, partial: true };
+
+    /// Builds an interval rounded outward by a few ulps, enough to cover the rounding of the
+    /// operation that produced `lo` and `hi`.
+    fn new(lo: f64, hi: f64, partial: bool) -> Interval {
+        if lo.is_nan() || hi.is_nan() {
+            return Interval::ENTIRE;
+        }
+        let widen = |v: f64| v.abs() * 4.0 * f64::EPSILON;
+        Interval { lo: lo - widen(lo), hi: hi + widen(hi), partial }
+    }
+
+    pub fn point(val: f64) -> Interval {
+        Interval { lo: val, hi: val, partial: false }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo > self.hi || self.lo.is_nan() || self.hi.is_nan()
+    }
+
+    pub fn contains(&self, val: f64) -> bool {
+        self.lo <= val && val <= self.hi
+    }
+
+    fn from_values(values: &[f64], partial: bool) -> Interval {
+        let lo = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        Interval::new(lo, hi, partial)
+    }
+
+    /// Restricts the interval to `min..=max`, marking it partial if anything was cut off.
+    fn restrict(&self, min: f64, max: f64) -> Interval {
+        if self.hi < min || self.lo > max {
+            return Interval::EMPTY;
+        }
+        Interval {
+            lo: self.lo.max(min),
+            hi: self.hi.min(max),
+            partial: self.partial || self.lo < min || self.hi > max,
+        }
+    }
+
+    fn monotone(&self, f: impl Fn(f64) -> f64, increasing: bool) -> Interval {
+        let (a, b) = (f(self.lo), f(self.hi));
+        if increasing {
+            Interval::new(a, b, self.partial)
+        } else {
+            Interval::new(b, a, self.partial)
+        }
+    }
+
+    /// Whether `offset + k * period` lies in the interval for some integer `k`.
+    fn hits(&self, offset: f64, period: f64) -> bool {
+        let k = ((self.lo - offset) / period).ceil();
+        offset + k * period <= self.hi
+    }
+
+    pub fn pow(self, exponent: Interval) -> Interval {
+        let partial = self.partial || exponent.partial;
+
+        if exponent.lo == exponent.hi && exponent.lo.fract() == 0.0 && exponent.lo.abs() < i32::MAX as f64 {
+            let n = exponent.lo as i32;
+            if n < 0 {
+                return Interval::point(1.0) / self.pow(Interval::point(-n as f64));
+            }
+            if n == 0 {
+                return Interval { lo: 1.0, hi: 1.0, partial };
+            }
+            if n % 2 == 1 || self.lo >= 0.0 {
+                return Interval::new(self.lo.powi(n), self.hi.powi(n), partial);
+            }
+            if self.hi <= 0.0 {
+                return Interval::new(self.hi.powi(n), self.lo.powi(n), partial);
+            }
+            return Interval::new(0.0, self.lo.abs().max(self.hi).powi(n), partial);
+        }
+
+        // Real powers are only defined for non-negative bases, where they are monotone in each
+        // argument so the extremes are at the corners
+        let base = self.restrict(0.0, f64::INFINITY);
+        if base.is_empty() {
+            return Interval::EMPTY;
+        }
+        Interval::from_values(
+            &[
+                base.lo.powf(exponent.lo),
+                base.lo.powf(exponent.hi),
+                base.hi.powf(exponent.lo),
+                base.hi.powf(exponent.hi),
+            ],
+            base.partial || partial,
+        )
+    }
+
+    pub fn apply(self, func: Func) -> Interval {
+        match func {
+            Func::Sin => self.sin(),
+            Func::Cos => self.cos(),
+            Func::Tan => {
+                if self.hi - self.lo >= PI || self.hits(FRAC_PI_2, PI) {
+                    return Interval::ENTIRE;
+                }
+                self.monotone(f64::tan, true)
+            }
+            Func::Asin => self.restrict(-1.0, 1.0).monotone(f64::asin, true),
+            Func::Acos => self.restrict(-1.0, 1.0).monotone(f64::acos, false),
+            Func::Atan => self.monotone(f64::atan, true),
+            Func::Sinh => self.monotone(f64::sinh, true),
+            Func::Cosh => self.even(f64::cosh),
+            Func::Tanh => self.monotone(f64::tanh, true),
+            Func::Sqrt => self.restrict(0.0, f64::INFINITY).monotone(f64::sqrt, true),
+            Func::Exp => self.monotone(f64::exp, true),
+            Func::Ln => self.restrict(0.0, f64::INFINITY).monotone(f64::ln, true),
+            Func::Log => self.restrict(0.0, f64::INFINITY).monotone(f64::log10, true),
+            Func::Abs => self.even(f64::abs),
+        }
+    }
+
+    /// Applies an even function that is increasing for positive arguments.
+    fn even(self, f: impl Fn(f64) -> f64) -> Interval {
+        if self.lo >= 0.0 {
+            self.monotone(f, true)
+        } else if self.hi <= 0.0 {
+            self.monotone(f, false)
+        } else {
+            Interval::new(f(0.0), f(self.lo).max(f(self.hi)), self.partial)
+        }
+    }
+
+    fn sin(self) -> Interval {
+        if self.hi - self.lo >= TAU {
+            return Interval { lo: -1.0, hi: 1.0, partial: self.partial };
+        }
+        let (a, b) = (self.lo.sin(), self.hi.sin());
+        let hi = if self.hits(FRAC_PI_2, TAU) { 1.0 } else { a.max(b) };
+        let lo = if self.hits(-FRAC_PI_2, TAU) { -1.0 } else { a.min(b) };
+        Interval::new(lo, hi, self.partial)
+    }
+
+    fn cos(self) -> Interval {
+        if self.hi - self.lo >= TAU {
+            return Interval { lo: -1.0, hi: 1.0, partial: self.partial };
+        }
+        let (a, b) = (self.lo.cos(), self.hi.cos());
+        let hi = if self.hits(0.0, TAU) { 1.0 } else { a.max(b) };
+        let lo = if self.hits(PI, TAU) { -1.0 } else { a.min(b) };
+        Interval::new(lo, hi, self.partial)
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval { lo: -self.hi, hi: -self.lo, partial: self.partial }
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(self.lo + other.lo, self.hi + other.hi, self.partial || other.partial)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval::new(self.lo - other.hi, self.hi - other.lo, self.partial || other.partial)
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, other: Interval) -> Interval {
+        // Zero times infinity is zero here, the infinity only ever stands for an unbounded end
+        let mul = |a: f64, b: f64| if a == 0.0 || b == 0.0 { 0.0 } else { a * b };
+        Interval::from_values(
+            &[mul(self.lo, other.lo), mul(self.lo, other.hi), mul(self.hi, other.lo), mul(self.hi, other.hi)],
+            self.partial || other.partial,
+        )
+    }
+}
+
+impl Div for Interval {
+    type Output = Interval;
+
+    fn div(self, other: Interval) -> Interval {
+        if other.contains(0.0) {
+            return if other.lo == 0.0 && other.hi == 0.0 { Interval::EMPTY } else { Interval::ENTIRE };
+        }
+        self * Interval::new(1.0 / other.hi, 1.0 / other.lo, other.partial)
+    }
+}
+
+/// Any operation on an empty interval is empty.
+fn unary(a: Interval, op: impl FnOnce(Interval) -> Interval) -> Interval {
+    if a.is_empty() { Interval::EMPTY } else { op(a) }
+}
+
+fn binary(a: Interval, b: Interval, op: impl FnOnce(Interval, Interval) -> Interval) -> Interval {
+    if a.is_empty() || b.is_empty() { Interval::EMPTY } else { op(a, b) }
+}
+
+impl Expr {
+    /// Encloses every value the expression takes when its variables range over the given
+    /// intervals. Unbound variables can take any value.
+    pub fn interval(&self, vars: &[(&str, Interval)]) -> Interval {
+        match self {
+            Expr::Num(val) => Interval::point(*val),
+            Expr::Var(name) => vars.iter().find(|(var, _)| *var == name.as_str()).map_or(Interval::ENTIRE, |(_, val)| *val),
+            Expr::Neg(a) => unary(a.interval(vars), |a| -a),
+            Expr::Add(a, b) => binary(a.interval(vars), b.interval(vars), |a, b| a + b),
+            Expr::Sub(a, b) => binary(a.interval(vars), b.interval(vars), |a, b| a - b),
+            Expr::Mul(a, b) => binary(a.interval(vars), b.interval(vars), |a, b| a * b),
+            Expr::Div(a, b) => binary(a.interval(vars), b.interval(vars), |a, b| a / b),
+            Expr::Pow(a, b) => binary(a.interval(vars), b.interval(vars), Interval::pow),
+            Expr::Call(func, a) => unary(a.interval(vars), |a| a.apply(*func)),
+        }
+    }
+}
+
+/// How many pixels of an interval plot were proven to contain the graph and how many could
+/// not be decided at pixel resolution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IntervalStats {
+    pub certain: usize,
+    pub ambiguous: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Inside,
+    Outside,
+    Undecided,
+}
+
+/// What the enclosure of `lhs - rhs` over a box proves about the relation there. Inequalities are
+/// only definitely inside where the function is also known to be defined over the whole box.
+fn classify(relation: Relation, value: Interval) -> Class {
+    let (is_inside, is_outside) = match relation {
+        Relation::Equal => (false, !value.contains(0.0)),
+        Relation::Less => (value.hi < 0.0, value.lo >= 0.0),
+        Relation::LessEqual => (value.hi <= 0.0, value.lo > 0.0),
+        Relation::Greater => (value.lo > 0.0, value.hi <= 0.0),
+        Relation::GreaterEqual => (value.lo >= 0.0, value.hi < 0.0),
+    };
+
+    if is_outside {
+        Class::Outside
+    } else if is_inside && !value.partial {
+        Class::Inside
+    } else {
+        Class::Undecided
+    }
+}
+
+/// Boxes of the window as pairs of opposite corners, sorted by what subdividing proved about them.
+#[derive(Default)]
+struct Regions {
+    inside: Vec<[(f64, f64); 2]>,
+    certain: Vec<[(f64, f64); 2]>,
+    ambiguous: Vec<[(f64, f64); 2]>,
+}
+
+/// Plots an equation or inequality by recursively subdividing the window and evaluating the
+/// expression over each box with interval arithmetic. Boxes are only discarded when the graph
+/// provably doesn't pass through them, so thin features and tangential roots are never lost.
+/// Pixels proven to contain a solution are drawn black, undecided pixels red.
+pub fn plot_interval<DB: DrawingBackend>(parsed: &Parsed, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<IntervalStats, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let (width, height) = chart.plotting_area().dim_in_pixel();
+    let regions = subdivide(parsed, settings, width, height)?;
+
+    chart.draw_series(regions.inside.iter().map(|area| Rectangle::new(*area, RGBAColor(0, 0, 0, 0.4).filled())))?;
+    chart.draw_series(regions.certain.iter().map(|area| Rectangle::new(*area, BLACK.filled())))?;
+    chart.draw_series(regions.ambiguous.iter().map(|area| Rectangle::new(*area, RED.mix(0.6).filled())))?;
+
+    Ok(IntervalStats { certain: regions.certain.len(), ambiguous: regions.ambiguous.len() })
+}
+
+/// Subdivides the window, `width` by `height` pixels, down to boxes that are decided or a pixel in size.
+fn subdivide(parsed: &Parsed, settings: &GraphSettings, width: u32, height: u32) -> Result<Regions, GraphError> {
+    let f = parsed.difference();
+
+    let mut variables = Vec::new();
+    f.variables(&mut variables);
+    if let Some(unknown) = variables.iter().find(|v| *v != "x" && *v != "y" && !settings.params.iter().any(|(p, _)| p == v)) {
+        return Err(GraphError::Evaluation(format!("unknown variable '{}'", unknown)));
+    }
+
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    let to_x = |i: u32| xmin + i as f64 / width as f64 * (xmax - xmin);
+    let to_y = |j: u32| ymax - j as f64 / height as f64 * (ymax - ymin);
+
+    let params: Vec<(&str, Interval)> = settings.params.iter().map(|(name, val)| (*name, Interval::point(*val))).collect();
+    let mut bindings = [&[("x", Interval::ENTIRE), ("y", Interval::ENTIRE)][..], &params].concat();
+    let mut point_bindings = [&[("x", 0.0), ("y", 0.0)][..], settings.params].concat();
+
+    let mut regions = Regions::default();
+
+    // Boxes of pixels as (left, top, right, bottom), exclusive on the right and bottom
+    let mut stack = vec![(0, 0, width, height)];
+
+    while let Some((i0, j0, i1, j1)) = stack.pop() {
+        let area = [(to_x(i0), to_y(j0)), (to_x(i1), to_y(j1))];
+        bindings[0].1 = Interval { lo: area[0].0, hi: area[1].0, partial: false };
+        bindings[1].1 = Interval { lo: area[1].1, hi: area[0].1, partial: false };
+
+        let value = f.interval(&bindings);
+        if value.is_empty() {
+            continue;
+        }
+
+        match classify(parsed.relation, value) {
+            Class::Outside => {}
+            Class::Inside => regions.inside.push(area),
+            Class::Undecided if i1 - i0 <= 1 && j1 - j0 <= 1 => {
+                // A sign change between the corners of a box the function is continuous on
+                // proves that the curve passes through it
+                let corners: Vec<f64> = [area[0], (area[1].0, area[0].1), (area[0].0, area[1].1), area[1]]
+                    .iter()
+                    .map(|&(x, y)| {
+                        point_bindings[0].1 = x;
+                        point_bindings[1].1 = y;
+                        f.eval(&point_bindings)
+                    })
+                    .collect();
+                let crosses = corners.iter().any(|v| *v <= 0.0) && corners.iter().any(|v| *v >= 0.0);
+
+                if parsed.relation == Relation::Equal && !value.partial && crosses {
+                    regions.certain.push(area);
+                } else {
+                    regions.ambiguous.push(area);
+                }
+            }
+            Class::Undecided => {
+                if i1 - i0 >= j1 - j0 {
+                    let mid = (i0 + i1) / 2;
+                    stack.push((i0, j0, mid, j1));
+                    stack.push((mid, j0, i1, j1));
+                } else {
+                    let mid = (j0 + j1) / 2;
+                    stack.push((i0, j0, i1, mid));
+                    stack.push((i0, mid, i1, j1));
+                }
+            }
+        }
+    }
+
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr;
+
+    fn interval(lo: f64, hi: f64) -> Interval {
+        Interval { lo, hi, partial: false }
+    }
+
+    /// Whether `a` encloses `lo..=hi` without being wider than rounding allows.
+    fn encloses(a: Interval, lo: f64, hi: f64) -> bool {
+        let slack = 1e-12 * lo.abs().max(hi.abs()).max(1.0);
+        a.lo <= lo && a.hi >= hi && a.lo >= lo - slack && a.hi <= hi + slack
+    }
+
+    #[test]
+    fn powers_of_intervals_containing_zero() {
+        let a = interval(-2.0, 3.0);
+        assert!(encloses(a.pow(Interval::point(2.0)), 0.0, 9.0));
+        assert!(encloses(a.pow(Interval::point(3.0)), -8.0, 27.0));
+        assert_eq!(a.pow(Interval::point(0.0)), Interval::point(1.0));
+        assert_eq!(a.pow(Interval::point(-1.0)), Interval::ENTIRE);
+        assert!(interval(0.0, 0.0).pow(Interval::point(-2.0)).is_empty());
+
+        // Real exponents cut off the negative part of the base
+        let root = a.pow(Interval::point(0.5));
+        assert!(encloses(root, 0.0, 3f64.sqrt()) && root.partial);
+    }
+
+    #[test]
+    fn division_by_intervals_containing_zero() {
+        assert!(encloses(interval(1.0, 2.0) / interval(2.0, 4.0), 0.25, 1.0));
+        assert!(encloses(interval(1.0, 2.0) / interval(-4.0, -2.0), -1.0, -0.25));
+        assert_eq!(interval(1.0, 2.0) / interval(-1.0, 1.0), Interval::ENTIRE);
+        assert_eq!(interval(1.0, 2.0) / interval(0.0, 1.0), Interval::ENTIRE);
+        assert!((interval(1.0, 2.0) / interval(0.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn trigonometric_enclosures() {
+        // Across a maximum, a minimum and both
+        assert!(encloses(interval(0.0, 3.0).apply(Func::Sin), 0.0f64.min(3f64.sin()), 1.0));
+        assert!(encloses(interval(4.0, 5.0).apply(Func::Sin), -1.0, 4f64.sin()));
+        assert!(encloses(interval(-0.5, 0.5).apply(Func::Cos), 0.5f64.cos(), 1.0));
+        assert!(encloses(interval(3.0, 3.5).apply(Func::Cos), -1.0, 3f64.cos().max(3.5f64.cos())));
+        assert!(encloses(interval(1.0, 9.0).apply(Func::Sin), -1.0, 1.0));
+        assert!(encloses(interval(-8.0, -1.0).apply(Func::Cos), -1.0, 1.0));
+
+        // Monotone between poles, everything across one
+        assert!(encloses(interval(-1.0, 1.0).apply(Func::Tan), (-1f64).tan(), 1f64.tan()));
+        assert_eq!(interval(1.0, 2.0).apply(Func::Tan), Interval::ENTIRE);
+        assert_eq!(interval(-2.0, -1.5).apply(Func::Tan), Interval::ENTIRE);
+        assert_eq!(interval(2.0, 5.5).apply(Func::Tan), Interval::ENTIRE);
+    }
+
+    #[test]
+    fn enclosures_contain_every_sample() {
+        let intervals = [(-0.3, 0.2), (1.0, 2.5), (2.0, 4.0), (-7.0, -4.5), (10.0, 10.1)];
+        for func in [Func::Sin, Func::Cos, Func::Tan] {
+            for (lo, hi) in intervals {
+                let enclosure = interval(lo, hi).apply(func);
+                for i in 0..=1000 {
+                    let x = lo + (hi - lo) * i as f64 / 1000.0;
+                    assert!(enclosure.contains(func.apply(x)), "{:?} of {}..{} misses {}", func, lo, hi, x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn classifies_boxes() {
+        assert_eq!(classify(Relation::Equal, interval(-1.0, 1.0)), Class::Undecided);
+        assert_eq!(classify(Relation::Equal, interval(0.5, 1.0)), Class::Outside);
+        assert_eq!(classify(Relation::Less, interval(-2.0, -1.0)), Class::Inside);
+        assert_eq!(classify(Relation::Less, interval(0.0, 1.0)), Class::Outside);
+        assert_eq!(classify(Relation::LessEqual, interval(-1.0, 0.0)), Class::Inside);
+        assert_eq!(classify(Relation::Greater, interval(-1.0, 1.0)), Class::Undecided);
+        assert_eq!(classify(Relation::GreaterEqual, interval(-1.0, -0.5)), Class::Outside);
+
+        // Where the function may be undefined nothing is definitely inside
+        assert_eq!(classify(Relation::Less, Interval { lo: -2.0, hi: -1.0, partial: true }), Class::Undecided);
+    }
+
+    fn regions(equation: &str) -> Regions {
+        let settings = GraphSettings {
+            path: "",
+            image_width: 64,
+            image_height: 64,
+            sim_window: (-2.0, 2.0, -2.0, 2.0),
+            yaw: 0.0,
+            params: &[],
+            refine: None,
+        };
+        subdivide(&expr::parse(equation).unwrap(), &settings, 64, 64).unwrap()
+    }
+
+    /// Whether a box lies within `distance` of the unit circle.
+    fn near_circle([(x0, y0), (x1, y1)]: [(f64, f64); 2], distance: f64) -> bool {
+        let (x, y) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+        ((x * x + y * y).sqrt() - 1.0).abs() <= distance
+    }
+
+    #[test]
+    fn tangential_roots_are_kept() {
+        // The sign never changes, so sign sampling finds nothing, but every pixel on the circle is
+        // kept. Only pixels with a corner exactly on the circle are certain.
+        let touching = regions("(x^2+y^2-1)^2=0");
+        assert!(touching.inside.is_empty());
+        assert!(touching.ambiguous.len() > 64 && touching.certain.len() < touching.ambiguous.len());
+        assert!(touching.certain.iter().chain(&touching.ambiguous).all(|area| near_circle(*area, 0.1)));
+
+        let crossing = regions("x^2+y^2=1");
+        assert!(crossing.certain.len() > 64);
+        assert!(crossing.certain.iter().chain(&crossing.ambiguous).all(|area| near_circle(*area, 0.1)));
+    }
+
+    #[test]
+    fn inequalities_fill_their_region() {
+        let disc = regions("x^2+y^2<1");
+        assert!(!disc.inside.is_empty());
+        assert!(disc.inside.iter().all(|&[(x0, y0), (x1, y1)]| [(x0, y0), (x1, y0), (x0, y1), (x1, y1)].iter().all(|(x, y)| x * x + y * y <= 1.0)));
+    }
+}
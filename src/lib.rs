@@ -0,0 +1,362 @@
+use mathsolver::equation::{CustomOperations, Node};
+use plotters::coord::ranged3d::Cartesian3d;
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+use plotters_bitmap::BitMapBackend;
+//use rayon::prelude::*;
+
+pub mod marching_squares;
+pub mod marching_cubes;
+pub mod plot;
+pub mod animate;
+pub mod html;
+pub mod watch;
+pub mod term;
+pub mod field;
+pub mod load;
+pub mod data;
+pub mod fit;
+pub mod expr;
+pub mod interval;
+pub mod error;
+pub mod timings;
+pub mod compile;
+pub mod diff;
+pub mod explicit;
+pub mod calculus;
+pub mod refine;
+pub mod intersect;
+pub mod features;
+pub mod report;
+
+pub use marching_squares::marching_squares;
+pub use marching_cubes::marching_cubes;
+pub use error::GraphError;
+use plot::*;
+use data::{DataPoint, draw_points};
+use interval::plot_interval;
+use timings::Timings;
+use report::Report;
+use field::parse_field;
+use explicit::parse_function;
+use calculus::{Overlays, Span};
+use intersect::{Contour, mark_intersections, mark_function_intersections};
+
+pub type ChartOn<'a, DB> = ChartContext<'a, DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
+pub type Chart<'a, 'b> = ChartOn<'a, BitMapBackend<'b>>;
+pub type Chart3D<'a, 'b> = ChartContext<'a, BitMapBackend<'b>, Cartesian3d<RangedCoordf64, RangedCoordf64, RangedCoordf64>>;
+
+pub type Root<'a> = DrawingArea<BitMapBackend<'a>, plotters::coord::Shift>;
+pub struct GraphSettings<'a> {
+    pub path: &'a str,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub sim_window: (f64, f64, f64, f64),
+    pub yaw: f64,
+    pub params: &'a [(&'a str, f64)],
+    /// How close Newton steps bring contour vertices to the curve, `None` to leave them where
+    /// contouring put them.
+    pub refine: Option<f64>,
+}
+
+pub fn create_root<'a>(settings: &GraphSettings<'a>) -> Result<Root<'a>, GraphError> {
+    let root = BitMapBackend::new(settings.path, (settings.image_width, settings.image_height)).into_drawing_area();
+    root.fill(&WHITE)?;
+    Ok(root)
+}
+
+pub fn create_graph<'a, DB: DrawingBackend>(settings: &GraphSettings, root: &'a DrawingArea<DB, plotters::coord::Shift>) -> Result<ChartOn<'a, DB>, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let mut chart = ChartBuilder::on(root)
+        .margin(10)
+        .set_all_label_area_size(30)
+        .build_cartesian_2d(settings.sim_window.0..settings.sim_window.1, settings.sim_window.2..settings.sim_window.3)?;
+
+    chart
+        .configure_mesh()
+        .label_style(("sans-serif", 15).into_font().color(&BLACK))
+        .axis_style(BLACK)
+        .draw()?;
+
+    Ok(chart)
+}
+
+pub fn create_graph_3d<'a, 'b>(settings: &GraphSettings<'a>, root: &'a Root<'b>) -> Result<Chart3D<'a, 'b>, GraphError> {
+    let mut chart = ChartBuilder::on(root)
+        .margin(10)
+        .set_all_label_area_size(30)
+        .build_cartesian_3d(settings.sim_window.0..settings.sim_window.1, settings.sim_window.2..settings.sim_window.3, settings.sim_window.0..settings.sim_window.1)?;
+
+    chart.with_projection(|mut pb| {
+        pb.yaw = settings.yaw;
+        pb.scale = 0.9;
+        pb.into_matrix()
+    });
+
+    chart
+        .configure_axes()
+        .label_style(("sans-serif", 15).into_font().color(&BLACK))
+        .max_light_lines(3)
+        .draw()?;
+
+    Ok(chart)
+}
+
+const DATA_COLORS: [RGBColor; 4] = [BLUE, RED, GREEN, MAGENTA];
+
+pub struct SubEqual;
+
+impl CustomOperations for SubEqual {
+    fn equal_f64(lhs: f64, rhs: f64) -> Node {
+        Node::Real(lhs - rhs)
+    }
+}
+
+/// An owned description of a graph that can be rendered without going through the command line.
+///
+/// ```no_run
+/// graph::Graph::new()
+///     .window(-2.0, 2.0, -2.0, 2.0)
+///     .equation("x^2+y^2=1")
+///     .render_to("circle.png")
+///     .unwrap();
+/// ```
+pub struct Graph {
+    image_width: u32,
+    image_height: u32,
+    sim_window: (f64, f64, f64, f64),
+    yaw: f64,
+    three_d: bool,
+    params: Vec<(String, f64)>,
+    equations: Vec<String>,
+    data: Vec<Vec<DataPoint>>,
+    connect_data: bool,
+    interval: bool,
+    explicit: bool,
+    overlays: Overlays,
+    refine: Option<f64>,
+    intersections: bool,
+}
+
+impl Default for Graph {
+    fn default() -> Graph {
+        Graph {
+            image_width: 600,
+            image_height: 600,
+            sim_window: (-1.0, 1.0, -1.0, 1.0),
+            yaw: 0.5,
+            three_d: false,
+            params: Vec::new(),
+            equations: Vec::new(),
+            data: Vec::new(),
+            connect_data: false,
+            interval: false,
+            explicit: false,
+            overlays: Overlays::default(),
+            refine: None,
+            intersections: false,
+        }
+    }
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Graph {
+        self.image_width = width;
+        self.image_height = height;
+        self
+    }
+
+    pub fn window(mut self, xmin: f64, xmax: f64, ymin: f64, ymax: f64) -> Graph {
+        self.sim_window = (xmin, xmax, ymin, ymax);
+        self
+    }
+
+    /// Plots the equations as surfaces in x, y and z instead of curves in x and y.
+    pub fn three_d(mut self, three_d: bool) -> Graph {
+        self.three_d = three_d;
+        self
+    }
+
+    /// Sets the rotation of the camera around the vertical axis of a 3D plot.
+    pub fn yaw(mut self, yaw: f64) -> Graph {
+        self.yaw = yaw;
+        self
+    }
+
+    /// Binds a free variable of the equations to a fixed value.
+    pub fn param(mut self, name: impl Into<String>, value: f64) -> Graph {
+        self.params.push((name.into(), value));
+        self
+    }
+
+    pub fn equation(mut self, equation: impl Into<String>) -> Graph {
+        self.equations.push(equation.into());
+        self
+    }
+
+    /// Overlays measured points on a 2D plot, each set in its own color.
+    pub fn data(mut self, points: Vec<DataPoint>) -> Graph {
+        self.data.push(points);
+        self
+    }
+
+    /// Connects the data points of each set with lines in the order they were given.
+    pub fn connect_data(mut self, connect: bool) -> Graph {
+        self.connect_data = connect;
+        self
+    }
+
+    /// Draws 2D plots with interval arithmetic instead of sampling, which never misses parts of
+    /// the graph and marks the pixels it can't decide.
+    pub fn interval(mut self, interval: bool) -> Graph {
+        self.interval = interval;
+        self
+    }
+
+    /// Reads the equations as functions `y = f(x)` and draws them along x, which shows steep and
+    /// discontinuous functions more faithfully than contouring `f(x) - y`.
+    pub fn explicit(mut self, explicit: bool) -> Graph {
+        self.explicit = explicit;
+        self
+    }
+
+    /// Draws the derivative of each explicit function on top of it, exact where the equation can
+    /// be differentiated symbolically and numeric otherwise.
+    pub fn derivative(mut self, derivative: bool) -> Graph {
+        self.overlays.derivative = derivative;
+        self
+    }
+
+    /// Draws the integral of each explicit function from `origin`.
+    pub fn integral(mut self, origin: f64) -> Graph {
+        self.overlays.integral = Some(origin);
+        self
+    }
+
+    /// Draws the tangent line of each explicit function at `x`.
+    pub fn tangent(mut self, x: f64) -> Graph {
+        self.overlays.tangents.push(x);
+        self
+    }
+
+    /// Draws the normal line of each explicit function at `x`.
+    pub fn normal(mut self, x: f64) -> Graph {
+        self.overlays.normals.push(x);
+        self
+    }
+
+    /// Shades the area under each explicit function over `span` and reports its size.
+    pub fn shade_under(mut self, span: Span) -> Graph {
+        self.overlays.shade = Some(span);
+        self
+    }
+
+    /// Shades the area between the first two explicit functions instead of under each of them.
+    pub fn shade_between(mut self, between: bool) -> Graph {
+        self.overlays.shade_between = between;
+        self
+    }
+
+    /// Sets how close contour vertices are moved to the curve, `None` (the default) to skip
+    /// refining them.
+    pub fn refine(mut self, tolerance: Option<f64>) -> Graph {
+        self.refine = tolerance;
+        self
+    }
+
+    /// Marks the roots, local extrema and inflection points of each explicit function and reports
+    /// a table of them.
+    pub fn features(mut self, features: bool) -> Graph {
+        self.overlays.features = features;
+        self
+    }
+
+    /// Marks and reports the points where the curves of the equations cross.
+    pub fn intersections(mut self, intersections: bool) -> Graph {
+        self.intersections = intersections;
+        self
+    }
+
+    pub fn render_to(&self, path: &str) -> Result<(), GraphError> {
+        self.render_timed(path).map(|_| ())
+    }
+
+    /// Renders like `render_to`, returning how long each stage took.
+    pub fn render_timed(&self, path: &str) -> Result<Timings, GraphError> {
+        self.render_report(path).map(|(timings, _)| timings)
+    }
+
+    /// Renders like `render_timed`, also returning the notable points, areas and intersections
+    /// found along the way and how many samples could not be evaluated.
+    pub fn render_report(&self, path: &str) -> Result<(Timings, Report), GraphError> {
+        let mut timings = Timings::default();
+        let mut report = Report::default();
+        let params: Vec<_> = self.params.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+        let settings = GraphSettings {
+            path,
+            image_width: self.image_width,
+            image_height: self.image_height,
+            sim_window: self.sim_window,
+            yaw: self.yaw,
+            params: &params,
+            refine: self.refine,
+        };
+
+        let root = timings.record("setup", || create_root(&settings))?;
+
+        if self.three_d {
+            let mut graph = timings.record("setup", || create_graph_3d(&settings, &root))?;
+            for equation in &self.equations {
+                let mut field = timings.record("parse", || parse_field(equation, settings.params))?;
+                plot_field_3d_timed(&mut field, &settings, &mut graph, &mut timings, &mut report)?;
+            }
+        } else {
+            let mut graph = timings.record("setup", || create_graph(&settings, &root))?;
+            if self.explicit {
+                // Drawn together, since shading can involve two of them
+                let mut functions = Vec::new();
+                for equation in &self.equations {
+                    functions.push((equation.as_str(), timings.record("parse", || parse_function(equation, settings.params))?));
+                }
+                timings.record("draw", || plot_functions(&mut functions, &self.overlays, &settings, &mut graph, &mut report))?;
+                if self.intersections {
+                    timings.record("intersect", || mark_function_intersections(&mut functions, &settings, &mut graph, &mut report))?;
+                }
+            } else {
+                // The fields and their contours are kept for finding intersections
+                let mut contours: Vec<Contour> = Vec::new();
+
+                for equation in &self.equations {
+                    if self.interval {
+                        let parsed = timings.record("parse", || expr::parse(equation)).map_err(|e| GraphError::parse(equation, e))?;
+                        timings.record("interval", || plot_interval(&parsed, &settings, &mut graph))?;
+                    } else {
+                        let mut field = timings.record("parse", || parse_field(equation, settings.params))?;
+                        let lines = plot_field_timed(&mut field, &settings, &mut graph, &mut timings, &mut report)?;
+                        contours.push((equation.as_str(), Box::new(field), lines));
+                    }
+                }
+
+                if self.intersections {
+                    timings.record("intersect", || mark_intersections(&mut contours, &settings, &mut graph, &mut report))?;
+                }
+            }
+            timings.record("draw", || -> Result<(), GraphError> {
+                for (i, points) in self.data.iter().enumerate() {
+                    draw_points(points, self.connect_data, &DATA_COLORS[i % DATA_COLORS.len()], &mut graph)?;
+                }
+                Ok(())
+            })?;
+        }
+
+        timings.record("save", || root.present())?;
+
+        Ok((timings, report))
+    }
+}
@@ -0,0 +1,347 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{GraphSettings, create_root, create_graph, create_graph_3d, marching_cubes};
+use crate::plot::{Grid, Surface, draw_grid, draw_surface, to_window_3d};
+use crate::error::GraphError;
+
+/// A grid of samples stored row-major with x varying fastest, then y, then z, the same layout
+/// `marching_squares` and `marching_cubes` expect.
+pub struct LoadedGrid {
+    pub dims: Dims,
+    pub values: Vec<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dims {
+    Flat(usize, usize),
+    Volume(usize, usize, usize),
+}
+
+impl Dims {
+    pub fn samples(&self) -> usize {
+        match *self {
+            Dims::Flat(w, h) => w * h,
+            Dims::Volume(w, h, d) => w * h * d,
+        }
+    }
+
+    /// Contouring needs at least one cell, so every dimension needs two samples.
+    pub fn is_contourable(&self) -> bool {
+        match *self {
+            Dims::Flat(w, h) => w >= 2 && h >= 2,
+            Dims::Volume(w, h, d) => w >= 2 && h >= 2 && d >= 2,
+        }
+    }
+}
+
+impl FromStr for Dims {
+    type Err = String;
+
+    /// Parses `WIDTHxHEIGHT` or `WIDTHxHEIGHTxDEPTH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sizes = s
+            .split('x')
+            .map(|v| v.trim().parse::<usize>().map_err(|e| format!("invalid size '{}': {}", v, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match sizes[..] {
+            _ if sizes.iter().any(|&size| size < 2) => Err(format!("every dimension in '{}' needs at least 2 samples", s)),
+            [w, h] => Ok(Dims::Flat(w, h)),
+            [w, h, d] => Ok(Dims::Volume(w, h, d)),
+            _ => Err(format!("expected WIDTHxHEIGHT or WIDTHxHEIGHTxDEPTH, got '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawType {
+    F32,
+    F64,
+}
+
+impl FromStr for RawType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(RawType::F32),
+            "f64" => Ok(RawType::F64),
+            _ => Err(format!("unknown sample type '{}', expected f32 or f64", s)),
+        }
+    }
+}
+
+/// Loads a grid, picking the format from the extension: `.csv`, `.npy`, and anything else is read
+/// as raw little-endian floats of `raw_type`. `dims` is required for raw files, optional for CSV
+/// files, where it reinterprets the values as a flattened grid, and checked against the shape of
+/// NumPy files.
+pub fn load_grid(path: &Path, dims: Option<Dims>, raw_type: RawType) -> Result<LoadedGrid, GraphError> {
+    let bytes = fs::read(path).map_err(|e| GraphError::file(path, e))?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    parse_grid(&bytes, extension, dims, raw_type).map_err(|e| GraphError::BadArgument(format!("{}: {}", path.display(), e)))
+}
+
+/// Reads a grid from the contents of a file with the given extension, see `load_grid`.
+fn parse_grid(bytes: &[u8], extension: Option<&str>, dims: Option<Dims>, raw_type: RawType) -> Result<LoadedGrid, String> {
+    let grid = match extension {
+        Some("csv") => load_csv(std::str::from_utf8(bytes).map_err(|e| e.to_string())?, dims)?,
+        Some("npy") => load_npy(bytes)?,
+        _ => match dims {
+            Some(dims) => LoadedGrid { dims, values: load_raw(bytes, raw_type)? },
+            None => return Err("the dimensions of a raw grid have to be given".to_string()),
+        },
+    };
+
+    if let Some(dims) = dims {
+        if dims != grid.dims {
+            return Err(format!("the grid has dimensions {:?}, not {:?}", grid.dims, dims));
+        }
+    }
+
+    if !grid.dims.is_contourable() {
+        return Err(format!("the grid has dimensions {:?}, but every dimension needs at least 2 samples", grid.dims));
+    }
+
+    if grid.values.len() != grid.dims.samples() {
+        return Err(format!("the grid holds {} samples but {:?} needs {}", grid.values.len(), grid.dims, grid.dims.samples()));
+    }
+
+    Ok(grid)
+}
+
+/// Every line is a row along x, the first line being the lowest y.
+fn load_csv(text: &str, dims: Option<Dims>) -> Result<LoadedGrid, String> {
+    let mut values = Vec::new();
+    let mut width = None;
+    let mut height = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row = line
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().map_err(|e| format!("line {}: invalid number '{}': {}", number + 1, v.trim(), e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match width {
+            None => width = Some(row.len()),
+            Some(width) if width != row.len() && dims.is_none() => {
+                return Err(format!("line {}: expected {} columns, got {}", number + 1, width, row.len()));
+            }
+            _ => {}
+        }
+
+        values.extend(row);
+        height += 1;
+    }
+
+    let dims = dims.unwrap_or(Dims::Flat(width.unwrap_or(0), height));
+
+    Ok(LoadedGrid { dims, values })
+}
+
+fn load_npy(bytes: &[u8]) -> Result<LoadedGrid, String> {
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err("not a NumPy file".into());
+    }
+
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+        version => return Err(format!("unsupported NumPy file version {}", version)),
+    };
+
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .ok_or("truncated NumPy header")?;
+    let header = std::str::from_utf8(header).map_err(|e| e.to_string())?;
+
+    if npy_field(header, "fortran_order")?.starts_with("True") {
+        return Err("Fortran ordered NumPy arrays are not supported".into());
+    }
+
+    let raw_type = match npy_field(header, "descr")?.trim_start_matches(['\'', '"']) {
+        descr if descr.starts_with("<f4") => RawType::F32,
+        descr if descr.starts_with("<f8") => RawType::F64,
+        descr => return Err(format!("unsupported NumPy dtype {}", descr.split(',').next().unwrap_or(descr))),
+    };
+
+    let shape = npy_field(header, "shape")?;
+    let shape = shape
+        .trim_start_matches('(')
+        .split(')')
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<usize>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // NumPy is row-major with the last axis varying fastest
+    let dims = match shape[..] {
+        [h, w] => Dims::Flat(w, h),
+        [d, h, w] => Dims::Volume(w, h, d),
+        _ => return Err(format!("expected a 2D or 3D array, got shape {:?}", shape)),
+    };
+
+    Ok(LoadedGrid {
+        dims,
+        values: load_raw(&bytes[header_start + header_len..], raw_type)?,
+    })
+}
+
+/// Returns the text following `'key':` in a NumPy header dictionary.
+fn npy_field<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let start = header
+        .find(&format!("'{}'", key))
+        .ok_or_else(|| format!("NumPy header is missing '{}'", key))?;
+    let value = &header[start + key.len() + 2..];
+    Ok(value.trim_start().trim_start_matches(':').trim_start())
+}
+
+fn load_raw(bytes: &[u8], raw_type: RawType) -> Result<Vec<f64>, String> {
+    let size = match raw_type {
+        RawType::F32 => 4,
+        RawType::F64 => 8,
+    };
+    let samples = bytes.chunks_exact(size);
+    if !samples.remainder().is_empty() {
+        return Err(format!("{} bytes are not a whole number of {}-byte samples", bytes.len(), size));
+    }
+
+    Ok(samples
+        .map(|b| match raw_type {
+            RawType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            RawType::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        })
+        .collect())
+}
+
+/// Contours a loaded grid spanning the simulation window, as curves for flat grids and as
+/// surfaces for volumes. Returns how many of the samples weren't finite, which are left out of
+/// the image as holes.
+pub fn render_grid(grid: LoadedGrid, settings: &GraphSettings) -> Result<usize, GraphError> {
+    let root = create_root(settings)?;
+    let invalid = grid.values.iter().filter(|val| !val.is_finite()).count();
+
+    match grid.dims {
+        Dims::Flat(width, height) => {
+            let mut graph = create_graph(settings, &root)?;
+            draw_grid(&Grid { width, height, values: grid.values, points: Vec::new(), invalid }, settings, &mut graph)?;
+        }
+        Dims::Volume(width, height, depth) => {
+            let mut graph = create_graph_3d(settings, &root)?;
+            let triangles = marching_cubes(width, height, depth, grid.values)
+                .into_iter()
+                .map(|triangle| triangle.map(|point| to_window_3d(settings, point)))
+                .collect();
+            draw_surface(&Surface { triangles, points: Vec::new(), normals: Vec::new(), invalid }, &mut graph)?;
+        }
+    }
+
+    root.present()?;
+
+    Ok(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(version: u8, header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x93NUMPY".to_vec();
+        bytes.extend([version, 0]);
+        match version {
+            1 => bytes.extend((header.len() as u16).to_le_bytes()),
+            _ => bytes.extend((header.len() as u32).to_le_bytes()),
+        }
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn f64_bytes(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn parse(bytes: &[u8], extension: &str, dims: Option<&str>) -> Result<LoadedGrid, String> {
+        parse_grid(bytes, Some(extension), dims.map(|dims| dims.parse().unwrap()), RawType::F64)
+    }
+
+    #[test]
+    fn reads_npy_headers() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let grid = parse(&npy(1, "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }\n", &f64_bytes(&values)), "npy", None).unwrap();
+        assert_eq!(grid.dims, Dims::Flat(3, 2));
+        assert_eq!(grid.values, values);
+
+        let values = [0.5f32, -1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let grid = parse(&npy(2, "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2, 2), }", &f32_bytes(&values)), "npy", Some("2x2x2")).unwrap();
+        assert_eq!(grid.dims, Dims::Volume(2, 2, 2));
+        assert_eq!(grid.values, values.map(f64::from));
+    }
+
+    #[test]
+    fn rejects_unsupported_npy_files() {
+        let data = f64_bytes(&[0.0; 4]);
+        let rejected = |header: &str| parse(&npy(1, header, &data), "npy", None).err().unwrap();
+
+        assert!(rejected("{'descr': '<f8', 'fortran_order': True, 'shape': (2, 2), }").contains("Fortran"));
+        assert!(rejected("{'descr': '>f8', 'fortran_order': False, 'shape': (2, 2), }").contains("dtype >f8"));
+        assert!(rejected("{'descr': '<i4', 'fortran_order': False, 'shape': (2, 2), }").contains("dtype <i4"));
+        assert!(rejected("{'fortran_order': False, 'shape': (2, 2), }").contains("missing 'descr'"));
+        assert!(rejected("{'descr': '<f8', 'fortran_order': False, 'shape': (4,), }").contains("2D or 3D"));
+        assert!(rejected("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }").contains("holds 4 samples"));
+
+        assert!(parse(b"NUMPY\x01\x00\x00\x00", "npy", None).is_err());
+        assert!(parse(&npy(1, "{'descr': '<f8'}", &[])[..12], "npy", None).err().unwrap().contains("truncated"));
+        assert!(parse(&npy(1, "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 2), }", &data), "npy", Some("2x3")).is_err());
+    }
+
+    #[test]
+    fn reads_raw_floats() {
+        let grid = parse_grid(&f32_bytes(&[1.0, 2.0, 3.0, 4.0]), None, Some(Dims::Flat(2, 2)), RawType::F32).unwrap();
+        assert_eq!(grid.values, [1.0, 2.0, 3.0, 4.0]);
+
+        let grid = parse(&f64_bytes(&[1.0; 8]), "bin", Some("2x2x2")).unwrap();
+        assert_eq!(grid.dims, Dims::Volume(2, 2, 2));
+    }
+
+    #[test]
+    fn rejects_raw_floats_that_do_not_fit() {
+        let bytes = f64_bytes(&[1.0; 6]);
+        assert!(parse(&bytes, "bin", None).err().unwrap().contains("dimensions"));
+        assert!(parse(&bytes, "bin", Some("2x2")).err().unwrap().contains("holds 6 samples"));
+        assert!(parse(&bytes[..47], "bin", Some("2x3")).err().unwrap().contains("whole number"));
+    }
+
+    #[test]
+    fn reads_csv() {
+        let grid = parse(b"1, 2, 3\n\n4, 5, 6\n", "csv", None).unwrap();
+        assert_eq!(grid.dims, Dims::Flat(3, 2));
+        assert_eq!(grid.values, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // Given dimensions reinterpret the values as a flattened grid, whatever the rows look like
+        let grid = parse(b"1, 2, 3, 4\n5, 6, 7, 8\n", "csv", Some("2x2x2")).unwrap();
+        assert_eq!(grid.dims, Dims::Volume(2, 2, 2));
+        assert!(parse(b"1, 2, 3\n4\n", "csv", Some("2x2")).is_ok());
+    }
+
+    #[test]
+    fn rejects_ragged_and_empty_csv() {
+        assert_eq!(parse(b"1, 2, 3\n4, 5\n", "csv", None).err().unwrap(), "line 2: expected 3 columns, got 2");
+        assert!(parse(b"1, 2\n3, x\n", "csv", None).err().unwrap().starts_with("line 2: invalid number 'x'"));
+        assert!(parse(b"", "csv", None).err().unwrap().contains("at least 2 samples"));
+        assert!(parse(b"\n \n", "csv", None).is_err());
+        assert!(parse(b"1, 2\n3, 4\n", "csv", Some("3x3")).err().unwrap().contains("holds 4 samples"));
+    }
+}
@@ -1,85 +1,54 @@
-use clap::{Arg, App};
-use mathsolver::equation::{Equation, CustomOperations, Node};
-use plotters::coord::ranged3d::Cartesian3d;
-use plotters::coord::types::RangedCoordf64;
-use plotters::prelude::*;
-use plotters_bitmap::BitMapBackend;
-//use rayon::prelude::*;
-use std::error::Error;
+use clap::{Arg, App, ArgMatches};
+use graph::{Graph, GraphError, GraphSettings};
+use graph::animate::{turntable, sweep, Sweep};
+use graph::data::load_points;
+use graph::field::parse_equation;
+use graph::fit::{fit, parse_guesses};
+use graph::html::slider_html;
+use graph::load::{load_grid, render_grid, Dims, RawType};
+use graph::term::plot_term;
+use graph::watch::watch;
+use std::fmt::Display;
 use std::path::Path;
+use std::process;
+use std::str::FromStr;
 
-mod marching_squares;
-mod marching_cubes;
-mod plot;
-
-use marching_squares::marching_squares;
-use marching_cubes::marching_cubes;
-use plot::*;
-
-pub type Chart<'a, 'b> = ChartContext<'a, BitMapBackend<'b>, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
-pub type Chart3D<'a, 'b> = ChartContext<'a, BitMapBackend<'b>, Cartesian3d<RangedCoordf64, RangedCoordf64, RangedCoordf64>>;
-
-pub type Root<'a> = DrawingArea<BitMapBackend<'a>, plotters::coord::Shift>;
-pub struct GraphSettings<'a> {
-    path: &'a str,
-    image_width: u32,
-    image_height: u32,
-    sim_window: (f64, f64, f64, f64)
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(e.exit_code());
+    }
 }
 
-fn create_root<'a>(settings: &GraphSettings<'a>) -> Result<Root<'a>, Box<dyn Error>> {
-    let root = BitMapBackend::new(settings.path, (settings.image_width, settings.image_height)).into_drawing_area();
-    root.fill(&WHITE)?;
-    Ok(root)
+/// Parses the value of an argument, reporting which argument was wrong.
+fn value<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<T, GraphError>
+where
+    T::Err: Display,
+{
+    parse_as(matches.value_of(name).unwrap_or_default(), name)
 }
 
-fn create_graph<'a, 'b>(settings: &GraphSettings<'a>, root: &'a Root<'b>) -> Result<Chart<'a, 'b>, Box<dyn Error>> {
-    let mut chart = ChartBuilder::on(root)
-        .margin(10)
-        .set_all_label_area_size(30)
-        .build_cartesian_2d(settings.sim_window.0..settings.sim_window.1, settings.sim_window.2..settings.sim_window.3)?;
-
-    chart
-        .configure_mesh()
-        .label_style(("sans-serif", 15).into_font().color(&BLACK))
-        .axis_style(&BLACK)
-        .draw()?;
-
-    Ok(chart)
+/// Parses one value of an argument that can be given several times.
+fn parse_as<T: FromStr>(value: &str, name: &str) -> Result<T, GraphError>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| GraphError::BadArgument(format!("invalid value '{}' for --{}: {}", value, name, e)))
 }
 
-fn create_graph_3d<'a, 'b>(settings: &GraphSettings<'a>, root: &'a Root<'b>) -> Result<Chart3D<'a, 'b>, Box<dyn Error>> {
-    let mut chart = ChartBuilder::on(root)
-        .margin(10)
-        .set_all_label_area_size(30)
-        .build_cartesian_3d(settings.sim_window.0..settings.sim_window.1, settings.sim_window.2..settings.sim_window.3, settings.sim_window.0..settings.sim_window.1)?;
-
-    chart.with_projection(|mut pb| {
-        pb.yaw = 0.5;
-        pb.scale = 0.9;
-        pb.into_matrix()
-    });
-
-    chart
-        .configure_axes()
-        .label_style(("sans-serif", 15).into_font().color(&BLACK))
-        .max_light_lines(3)
-        .draw()?;
-
-    Ok(chart)
-}
-struct SubEqual;
-
-impl CustomOperations for SubEqual {
-    fn equal_f64(lhs: f64, rhs: f64) -> Node {
-        Node::Real(lhs - rhs)
+/// Tells the user how much of the window could not be evaluated, since those parts are simply
+/// missing from the image.
+fn warn_invalid(invalid: usize) {
+    if invalid > 0 {
+        eprintln!("warning: {} samples could not be evaluated and were left as holes", invalid);
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn run() -> Result<(), GraphError> {
     let matches = App::new("Graph App")
         .version("1.0")
         .author("Ludwig Bogsveen")
+        .after_help("EXIT CODES:\n    2  bad argument or input file\n    3  the equation could not be parsed\n    4  the equation could not be evaluated\n    5  drawing failed\n    6  reading or writing a file failed")
         .arg(Arg::with_name("width")
             .short('w')
             .long("width")
@@ -132,7 +101,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             .value_name("EQUATION")
             .takes_value(true)
             .default_value("x^pi=y")
-            .help("The equation to graph"))
+            .multiple_occurrences(true)
+            .help("The equation to graph, can be given several times"))
         .arg(Arg::with_name("path")
             .long("path")
             .short('p')
@@ -140,44 +110,257 @@ fn main() -> Result<(), Box<dyn Error>> {
             .takes_value(true)
             .default_value("images/graph.png")
             .help("The path to save the graphs in"))
+        .arg(Arg::with_name("turntable")
+            .long("turntable")
+            .short('t')
+            .value_name("FRAMES")
+            .takes_value(true)
+            .help("Renders the equation in 3D as a rotating GIF with the given number of frames"))
+        .arg(Arg::with_name("frame-delay")
+            .long("frame-delay")
+            .value_name("MILLISECONDS")
+            .takes_value(true)
+            .default_value("50")
+            .help("The delay between frames of an animation"))
+        .arg(Arg::with_name("animate")
+            .long("animate")
+            .short('a')
+            .value_name("NAME=START:END:STEP")
+            .takes_value(true)
+            .help("Sweeps a free variable of the equation, writing a GIF or a numbered image sequence"))
+        .arg(Arg::with_name("3d")
+            .long("3d")
+            .help("Plots the equation as a surface in x, y and z"))
+        .arg(Arg::with_name("html")
+            .long("html")
+            .requires("animate")
+            .conflicts_with("3d")
+            .help("Writes the sweep as a self-contained HTML page with a slider instead of images"))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .value_name("SPEC")
+            .takes_value(true)
+            .help("Renders the equations and window from a spec file and re-renders whenever it changes"))
+        .arg(Arg::with_name("term")
+            .long("term")
+            .help("Prints the graph to the terminal instead of saving an image"))
+        .arg(Arg::with_name("data")
+            .long("data")
+            .short('d')
+            .value_name("FILE")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .help("Draws the points of a CSV file with x,y or x,y,error columns on top of the graph"))
+        .arg(Arg::with_name("data-lines")
+            .long("data-lines")
+            .help("Connects the data points with lines"))
+        .arg(Arg::with_name("fit")
+            .long("fit")
+            .value_name("NAME=GUESS,...")
+            .takes_value(true)
+            .requires("data")
+            .help("Fits the named parameters of the equation to the data, starting from the given guesses"))
+        .arg(Arg::with_name("interval")
+            .long("interval")
+            .short('i')
+            .help("Plots with interval arithmetic, drawing every pixel the graph may pass through"))
+        .arg(Arg::with_name("grid")
+            .long("grid")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Contours a grid of samples from a .csv, .npy or raw little-endian float file spanning the window"))
+        .arg(Arg::with_name("dims")
+            .long("dims")
+            .value_name("WIDTHxHEIGHT[xDEPTH]")
+            .takes_value(true)
+            .help("The dimensions of the grid, required for raw files"))
+        .arg(Arg::with_name("raw-type")
+            .long("raw-type")
+            .value_name("TYPE")
+            .takes_value(true)
+            .default_value("f32")
+            .possible_values(["f32", "f64"])
+            .help("The sample type of raw grid files"))
+        .arg(Arg::with_name("explicit")
+            .long("explicit")
+            .conflicts_with_all(&["3d", "interval"])
+            .help("Reads the equations as y = f(x) and plots them along x"))
+        .arg(Arg::with_name("derivative")
+            .long("derivative")
+            .requires("explicit")
+            .help("Draws the derivative of each function in blue"))
+        .arg(Arg::with_name("integral")
+            .long("integral")
+            .value_name("ORIGIN")
+            .takes_value(true)
+            .requires("explicit")
+            .help("Draws the integral of each function from ORIGIN in green"))
+        .arg(Arg::with_name("tangent")
+            .long("tangent")
+            .value_name("X")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .requires("explicit")
+            .help("Draws the tangent line of each function at X in red, can be given several times"))
+        .arg(Arg::with_name("normal")
+            .long("normal")
+            .value_name("X")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .requires("explicit")
+            .help("Draws the normal line of each function at X in magenta, can be given several times"))
+        .arg(Arg::with_name("shade-under")
+            .long("shade-under")
+            .value_name("START:END")
+            .takes_value(true)
+            .requires("explicit")
+            .help("Shades the area under each function between START and END and prints its signed size"))
+        .arg(Arg::with_name("shade-between")
+            .long("shade-between")
+            .requires("shade-under")
+            .help("Shades the area between the first two functions instead of under each"))
+        .arg(Arg::with_name("features")
+            .long("features")
+            .requires("explicit")
+            .help("Marks the roots, extrema and inflection points of each function and prints a table of them"))
+        .arg(Arg::with_name("intersections")
+            .long("intersections")
+            .conflicts_with_all(&["3d", "interval"])
+            .help("Marks and prints the points where the curves of the equations cross. Not available for 3D surfaces, which meet along curves, or with --interval, which shades regions without tracing curves"))
+        .arg(Arg::with_name("tolerance")
+            .long("tolerance")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .help("Moves contour vertices onto the curve with Newton steps until they are within DISTANCE of it"))
+        .arg(Arg::with_name("timings")
+            .long("timings")
+            .help("Prints how long each stage of rendering took to stderr"))
         .get_matches();
 
-    let width = matches.value_of("width").unwrap().parse().unwrap();
-    let height = matches.value_of("height").unwrap().parse().unwrap();
-    let xmin = matches.value_of("xmin").unwrap().parse().unwrap();
-    let xmax = matches.value_of("xmax").unwrap().parse().unwrap();
-    let ymin = matches.value_of("ymin").unwrap().parse().unwrap();
-    let ymax = matches.value_of("ymax").unwrap().parse().unwrap();
-    let eq = matches.value_of("equation").unwrap();
+    if let Some(spec) = matches.value_of("watch") {
+        return watch(Path::new(spec));
+    }
+
+    let width = value(&matches, "width")?;
+    let height = value(&matches, "height")?;
+    let xmin = value(&matches, "xmin")?;
+    let xmax = value(&matches, "xmax")?;
+    let ymin = value(&matches, "ymin")?;
+    let ymax = value(&matches, "ymax")?;
+    let equations: Vec<_> = matches.values_of("equation").unwrap().collect();
     let path = matches.value_of("path").unwrap();
+    let refine = matches.is_present("tolerance").then(|| value(&matches, "tolerance")).transpose()?;
 
     let graph_settings = GraphSettings {
         path,
         image_width: width,
         image_height: height,
         sim_window: (xmin, xmax, ymin, ymax),
+        yaw: 0.5,
+        params: &[],
+        refine,
     };
 
-    let mut eq = Equation::new(eq);
+    if let Some(grid_path) = matches.value_of("grid") {
+        let dims = matches.is_present("dims").then(|| value::<Dims>(&matches, "dims")).transpose()?;
+        let raw_type: RawType = value(&matches, "raw-type")?;
+        warn_invalid(render_grid(load_grid(Path::new(grid_path), dims, raw_type)?, &graph_settings)?);
+        println!("{}", Path::new(path).canonicalize()?.display());
+        return Ok(());
+    }
 
-    //plot(&mut eq, &graph_settings)?;
+    // For the modes that evaluate the equations with mathsolver directly
+    let parse_all = || equations.iter().map(|eq| parse_equation(eq)).collect::<Result<Vec<_>, _>>();
 
-    let root = create_root(&graph_settings)?;
-    let mut graph = create_graph_3d(&graph_settings, &root)?;
+    if matches.is_present("term") {
+        return plot_term(&mut parse_all()?, &graph_settings);
+    }
 
-    //plot_3d(&mut eq, &graph_settings, &mut graph)?;
-    //plot_x(&mut eq, &graph_settings, &mut graph);
+    if matches.is_present("turntable") {
+        let frames: u32 = value(&matches, "turntable")?;
+        if frames == 0 {
+            return Err(GraphError::BadArgument("--turntable needs at least one frame".to_string()));
+        }
+        let frame_delay = value(&matches, "frame-delay")?;
+        turntable(&mut parse_all()?, &graph_settings, frames, frame_delay)?;
+        println!("{}", Path::new(path).canonicalize()?.display());
+        return Ok(());
+    }
 
-    //plot_3d(&mut Equation::new("x^2+z^2=0.15"), &graph_settings, &mut graph)?;
-    plot_3d(&mut Equation::new("x^2+z^2=0.15"), &graph_settings, &mut graph)?;
-    plot_3d(&mut Equation::new("(x+0.5)^2+(y+0.7)^2+z^2=0.15"), &graph_settings, &mut graph)?;
-    plot_3d(&mut Equation::new("(x-0.5)^2+(y+0.7)^2+z^2=0.15"), &graph_settings, &mut graph)?;
-    plot_3d(&mut Equation::new("x^2+(y-0.9)^2+z^2=0.15"), &graph_settings, &mut graph)?;
-    //plot_3d(&mut Equation::new("(0.15/0.15)*sqrt(x^2+z^2)=y"), &graph_settings, &mut graph)?;
+    if matches.is_present("animate") {
+        let spec: Sweep = value(&matches, "animate")?;
+        let mut eqs = parse_all()?;
+        if matches.is_present("html") {
+            slider_html(&mut eqs, &graph_settings, &spec)?;
+            println!("{}", Path::new(path).canonicalize()?.display());
+            return Ok(());
+        }
+        let frame_delay = value(&matches, "frame-delay")?;
+        sweep(&mut eqs, &graph_settings, &spec, matches.is_present("3d"), frame_delay)?;
+        return Ok(());
+    }
 
-    root.present()?;
+    let mut graph = equations.iter().fold(Graph::new(), |graph, eq| graph.equation(*eq));
+    for data in matches.values_of("data").into_iter().flatten() {
+        graph = graph.data(load_points(Path::new(data))?);
+    }
+
+    if matches.is_present("integral") {
+        graph = graph.integral(value(&matches, "integral")?);
+    }
+    if matches.is_present("shade-under") {
+        graph = graph.shade_under(value(&matches, "shade-under")?);
+    }
+    for x in matches.values_of("tangent").into_iter().flatten() {
+        graph = graph.tangent(parse_as(x, "tangent")?);
+    }
+    for x in matches.values_of("normal").into_iter().flatten() {
+        graph = graph.normal(parse_as(x, "normal")?);
+    }
+
+    if let Some(guesses) = matches.value_of("fit") {
+        let guesses = parse_guesses(guesses).map_err(|e| GraphError::BadArgument(format!("invalid value for --fit: {}", e)))?;
+        let points: Vec<_> = matches
+            .values_of("data")
+            .into_iter()
+            .flatten()
+            .map(|data| load_points(Path::new(data)))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+
+        let fitted = fit(&mut parse_equation(equations[0])?, &points, &guesses)?;
+        println!("{}", fitted);
+
+        for (name, value) in fitted.params {
+            graph = graph.param(name, value);
+        }
+    }
+
+    let (timings, report) = graph
+        .connect_data(matches.is_present("data-lines"))
+        .size(width, height)
+        .window(xmin, xmax, ymin, ymax)
+        .three_d(matches.is_present("3d"))
+        .interval(matches.is_present("interval"))
+        .explicit(matches.is_present("explicit"))
+        .derivative(matches.is_present("derivative"))
+        .shade_between(matches.is_present("shade-between"))
+        .features(matches.is_present("features"))
+        .intersections(matches.is_present("intersections"))
+        .refine(refine)
+        .render_report(path)?;
+
+    if matches.is_present("timings") {
+        eprintln!("{}", timings);
+    }
+
+    warn_invalid(report.invalid);
+    for (name, line) in &report.missing_lines {
+        eprintln!("warning: {}: {}", name, line);
+    }
+    print!("{}", report);
 
-    println!("{}", Path::new(path).canonicalize()?.as_os_str().to_str().unwrap());
+    println!("{}", Path::new(path).canonicalize()?.display());
 
     Ok(())
 }
\ No newline at end of file
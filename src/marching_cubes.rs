@@ -1,16 +1,17 @@
-type Triangle = [(f64, f64, f64); 3];
+pub type Triangle = [(f64, f64, f64); 3];
 
 struct GridCell {
     p: [(f64, f64, f64); 8],
     val: [f64; 8],
 }
 
+#[allow(clippy::identity_op)]
 pub fn marching_cubes(
     width: usize,
     height: usize,
     depth: usize,
     value_grid: Vec<f64>,
-) -> Vec<[(f64, f64, f64); 3]> {
+) -> Vec<Triangle> {
     let scale_x = |x: f64| -> f64 { (x / (width - 1) as f64) * 2.0 };
 
     let scale_y = |y: f64| -> f64 { (y / (height - 1) as f64) * 2.0 };
@@ -34,7 +35,12 @@ pub fn marching_cubes(
                     value(x + 1, y + 1, z + 0),
                     value(x + 0, y + 1, z + 0),
                 ];
-                
+
+                // Cells touching a sample that could not be evaluated are left out
+                if values.iter().any(|val| !val.is_finite()) {
+                    continue;
+                }
+
                 let x = scale_x(x as f64) - 1.0;
                 let y = scale_y(y as f64) - 1.0;
                 let z = scale_z(z as f64) - 1.0;
@@ -68,6 +74,7 @@ pub fn marching_cubes(
    of totally below the isolevel.
 */
 
+#[allow(clippy::identity_op)]
 fn polygonise(grid: GridCell, isolevel: f64, triangles: &mut Vec<Triangle>) {
     /*
        Determine the index into the edge table which
@@ -169,7 +176,6 @@ fn vertex_interp(
     valp1: f64,
     valp2: f64,
 ) -> (f64, f64, f64) {
-    let mu: f64;
     let mut p: (f64, f64, f64) = (0.0, 0.0, 0.0);
 
     if (isolevel - valp1).abs() < 0.00001 {
@@ -181,12 +187,12 @@ fn vertex_interp(
     if (valp1 - valp2).abs() < 0.00001 {
         return p1;
     }
-    mu = (isolevel - valp1) / (valp2 - valp1);
+    let mu = (isolevel - valp1) / (valp2 - valp1);
     p.0 = p1.0 + mu * (p2.0 - p1.0);
     p.1 = p1.1 + mu * (p2.1 - p1.1);
     p.2 = p1.2 + mu * (p2.2 - p1.2);
 
-    return p;
+    p
 }
 
 const EDGE_TABLE: [i32; 256] = [
@@ -474,3 +480,120 @@ const TRIANGLE_TABLE: [[i32; 16]; 256] = [
         -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
     ],
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// Samples `f` on an `n` by `n` by `n` grid spanning the same `-1.0..=1.0` cube the output is in.
+    fn sample(n: usize, f: impl Fn(f64, f64, f64) -> f64) -> Vec<f64> {
+        let coord = |i: usize| 2.0 * i as f64 / (n - 1) as f64 - 1.0;
+        (0..n * n * n).map(|i| f(coord(i % n), coord(i / n % n), coord(i / (n * n)))).collect()
+    }
+
+    fn key((x, y, z): (f64, f64, f64)) -> (i64, i64, i64) {
+        ((x / EPSILON).round() as i64, (y / EPSILON).round() as i64, (z / EPSILON).round() as i64)
+    }
+
+    fn sub(a: (f64, f64, f64), b: (f64, f64, f64)) -> (f64, f64, f64) {
+        (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+    }
+
+    fn cross(a: (f64, f64, f64), b: (f64, f64, f64)) -> (f64, f64, f64) {
+        (a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0)
+    }
+
+    fn dot(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+        a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+    }
+
+    fn sphere(n: usize, r: f64) -> Vec<Triangle> {
+        marching_cubes(n, n, n, sample(n, |x, y, z| x * x + y * y + z * z - r * r))
+    }
+
+    // The radius keeps every sample well away from zero, so no vertex snaps onto a corner
+    const RADIUS: f64 = 0.708;
+
+    #[test]
+    fn every_case_of_a_single_cube() {
+        let corners = [
+            (0.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 0.0, 0.0), (0.0, 0.0, 0.0),
+            (0.0, 1.0, 1.0), (1.0, 1.0, 1.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0),
+        ];
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 0), (4, 5), (5, 6), (6, 7), (7, 4), (0, 4), (1, 5), (2, 6), (3, 7)];
+
+        for index in 0..256 {
+            let val = [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| if index & (1 << corner) != 0 { -1.0 } else { 1.0 });
+            let mut triangles = Vec::new();
+            polygonise(GridCell { p: corners, val }, 0.0, &mut triangles);
+
+            if index == 0 || index == 255 {
+                assert!(triangles.is_empty(), "case {} is entirely on one side", index);
+            } else {
+                assert!(!triangles.is_empty(), "case {} has no triangles", index);
+            }
+
+            // With values of ±1 every vertex is the middle of an edge that changes sign
+            for point in triangles.iter().flatten() {
+                assert!(
+                    edges.iter().any(|&(a, b)| {
+                        let (pa, pb) = (corners[a], corners[b]);
+                        let middle = ((pa.0 + pb.0) / 2.0, (pa.1 + pb.1) / 2.0, (pa.2 + pb.2) / 2.0);
+                        key(*point) == key(middle) && (val[a] < 0.0) != (val[b] < 0.0)
+                    }),
+                    "case {}: {:?} is not on an edge that changes sign",
+                    index,
+                    point
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_area() {
+        let area: f64 = sphere(41, RADIUS)
+            .iter()
+            .map(|[a, b, c]| {
+                let n = cross(sub(*b, *a), sub(*c, *a));
+                dot(n, n).sqrt() / 2.0
+            })
+            .sum();
+
+        let expected = 4.0 * PI * RADIUS * RADIUS;
+        assert!((area - expected).abs() / expected < 0.02, "area {} instead of {}", area, expected);
+    }
+
+    #[test]
+    fn sphere_volume() {
+        // Divergence theorem, summing the signed volumes of the tetrahedra from the origin
+        let volume: f64 = sphere(41, RADIUS).iter().map(|[a, b, c]| dot(*a, cross(*b, *c)) / 6.0).sum::<f64>().abs();
+
+        let expected = 4.0 / 3.0 * PI * RADIUS.powi(3);
+        assert!((volume - expected).abs() / expected < 0.02, "volume {} instead of {}", volume, expected);
+    }
+
+    #[test]
+    fn sphere_is_watertight() {
+        let mut edges = HashMap::new();
+
+        for triangle in sphere(41, RADIUS) {
+            let keys = triangle.map(key);
+            if keys[0] == keys[1] || keys[1] == keys[2] || keys[2] == keys[0] {
+                continue;
+            }
+
+            for (a, b) in [(keys[0], keys[1]), (keys[1], keys[2]), (keys[2], keys[0])] {
+                *edges.entry(if a < b { (a, b) } else { (b, a) }).or_insert(0) += 1;
+            }
+        }
+
+        assert!(!edges.is_empty());
+        // Every edge of a closed surface is shared by exactly two triangles
+        assert!(edges.values().all(|&count| count == 2), "the surface has holes");
+    }
+}
@@ -12,6 +12,16 @@ pub fn marching_squares(width: usize, height: usize, value_grid: Vec<f64>) -> Ve
 
     for y in 0..height-1 {
         for x in 0..width-1 {
+            // Cells touching a sample that could not be evaluated are left out, a hole rather than a guess
+            if !cell_is_finite(&value_grid, width, x, y) {
+                continue;
+            }
+
+            // The sign also flips across poles like the one of `1/x`, which are not part of the curve
+            if cell_has_pole(&value_grid, width, height, x, y) {
+                continue;
+            }
+
             let a = value_grid[x+y*width] < 0.0;
             let b = value_grid[x+1+y*width] < 0.0;
             let c = value_grid[x+(y+1)*width] < 0.0;
@@ -39,10 +49,272 @@ pub fn marching_squares(width: usize, height: usize, value_grid: Vec<f64>) -> Ve
                     lines.push([(x + scale_x(0.5), y), (x + scale_x(1.0), y + scale_y(0.5))]);
                 },
                 0b1011 => lines.push([(x + scale_x(0.5), y), (x + scale_x(1.0), y + scale_y(0.5))]),
-                _ => panic!()
+                _ => unreachable!()
             }
         }
     }
 
+    touching_lines(width, height, &value_grid, &mut lines);
+
     lines
-}
\ No newline at end of file
+}
+
+/// How far, in grid steps, the fitted parabola may pass by zero and still count as touching it.
+/// A field like `k * (d^2 + gap^2)`, `d` being the distance to a curve, misses the curve by `gap`.
+/// Measuring that in grid steps rather than against the neighbouring values keeps the test the
+/// same at every zoom, and double roots that aren't exactly quadratic along the grid leave an
+/// error in the vertex well below this.
+const TOUCH_TOLERANCE: f64 = 0.25;
+
+/// Finds where the field touches zero without changing sign, like the double root of
+/// `(y-x^2)^2=0`, which the sign test above can never see. Along every row and column a parabola
+/// is fitted through each local minimum of `|f|` and its two neighbours, and if the vertex of the
+/// parabola reaches zero that point is on the curve. Cells with such points on two of their edges
+/// get a segment between them.
+fn touching_lines(width: usize, height: usize, value_grid: &[f64], lines: &mut Vec<[(f64, f64); 2]>) {
+    let scale_x = |x: f64| -> f64 {
+        (x / (width - 1) as f64) * 2.0
+    };
+
+    let scale_y = |y: f64| -> f64 {
+        (y / (height - 1) as f64) * 2.0
+    };
+
+    let value = |x: usize, y: usize| value_grid[x + y * width];
+
+    // Touching points as offsets along the rows and columns, in units of grid steps
+    let mut row_touches = vec![Vec::new(); height];
+    let mut column_touches = vec![Vec::new(); width];
+
+    for (y, touches) in row_touches.iter_mut().enumerate() {
+        for x in 1..width.saturating_sub(1) {
+            if let Some(t) = touch(value(x - 1, y), value(x, y), value(x + 1, y)) {
+                touches.push(x as f64 + t);
+            }
+        }
+    }
+
+    for (x, touches) in column_touches.iter_mut().enumerate() {
+        for y in 1..height.saturating_sub(1) {
+            if let Some(t) = touch(value(x, y - 1), value(x, y), value(x, y + 1)) {
+                touches.push(y as f64 + t);
+            }
+        }
+    }
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            if !cell_is_finite(value_grid, width, x, y) {
+                continue;
+            }
+
+            let corners = [value(x, y), value(x + 1, y), value(x, y + 1), value(x + 1, y + 1)];
+            if corners.iter().any(|v| *v < 0.0) && corners.iter().any(|v| *v >= 0.0) {
+                // Already crossed by the regular contour
+                continue;
+            }
+
+            let on_edge = |t: &f64, start: usize| *t >= start as f64 && *t < (start + 1) as f64;
+
+            let points: Vec<(f64, f64)> = row_touches[y].iter().filter(|t| on_edge(t, x)).map(|t| (*t, y as f64))
+                .chain(row_touches[y + 1].iter().filter(|t| on_edge(t, x)).map(|t| (*t, (y + 1) as f64)))
+                .chain(column_touches[x].iter().filter(|t| on_edge(t, y)).map(|t| (x as f64, *t)))
+                .chain(column_touches[x + 1].iter().filter(|t| on_edge(t, y)).map(|t| ((x + 1) as f64, *t)))
+                .collect();
+
+            for pair in points.chunks_exact(2) {
+                lines.push([
+                    (scale_x(pair[0].0) - 1.0, scale_y(pair[0].1) - 1.0),
+                    (scale_x(pair[1].0) - 1.0, scale_y(pair[1].1) - 1.0),
+                ]);
+            }
+        }
+    }
+}
+
+/// Returns the offset from the middle sample to where `|f|` reaches zero, if the three samples
+/// have a minimum of `|f|` in the middle that the fitted parabola takes down to zero.
+fn touch(a: f64, b: f64, c: f64) -> Option<f64> {
+    let same_sign = (a > 0.0 && c > 0.0 && b >= 0.0) || (a < 0.0 && c < 0.0 && b <= 0.0);
+    if !same_sign || b.abs() > a.abs() || b.abs() >= c.abs() {
+        return None;
+    }
+
+    let curvature = a - 2.0 * b + c;
+    if curvature == 0.0 {
+        return None;
+    }
+
+    let t = 0.5 * (a - c) / curvature;
+    let vertex = b - (a - c) * (a - c) / (8.0 * curvature);
+
+    // The parabola is `vertex + curvature / 2 * s^2` around its vertex, so it misses zero by
+    // `sqrt(2 * vertex / curvature)` steps, or reaches it if the vertex is on the other side
+    let gap_squared = 2.0 * vertex / curvature;
+    if gap_squared <= TOUCH_TOLERANCE * TOUCH_TOLERANCE {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+fn cell_is_finite(value_grid: &[f64], width: usize, x: usize, y: usize) -> bool {
+    [x + y * width, x + 1 + y * width, x + (y + 1) * width, x + 1 + (y + 1) * width]
+        .iter()
+        .all(|&i| value_grid[i].is_finite())
+}
+
+fn cell_has_pole(value_grid: &[f64], width: usize, height: usize, x: usize, y: usize) -> bool {
+    let value = |x: usize, y: usize| value_grid[x + y * width];
+    let row = |x: usize, y: usize| (x < width).then(|| value(x, y));
+    let column = |x: usize, y: usize| (y < height).then(|| value(x, y));
+
+    let before_x = x.checked_sub(1);
+    let before_y = y.checked_sub(1);
+
+    pole_between(before_x.and_then(|bx| row(bx, y)), value(x, y), value(x + 1, y), row(x + 2, y))
+        || pole_between(before_x.and_then(|bx| row(bx, y + 1)), value(x, y + 1), value(x + 1, y + 1), row(x + 2, y + 1))
+        || pole_between(before_y.and_then(|by| column(x, by)), value(x, y), value(x, y + 1), column(x, y + 2))
+        || pole_between(before_y.and_then(|by| column(x + 1, by)), value(x + 1, y), value(x + 1, y + 1), column(x + 1, y + 2))
+}
+
+/// Whether the sign change between `a` and `b` comes from the field blowing up rather than
+/// crossing zero. Approaching a root `|f|` shrinks, approaching a pole it grows from both sides
+/// and the jump across it is steeper than the steps leading up to it.
+fn pole_between(before: Option<f64>, a: f64, b: f64, after: Option<f64>) -> bool {
+    let (before, after) = match (before, after) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return false,
+    };
+
+    (a < 0.0) != (b < 0.0)
+        && (before < 0.0) == (a < 0.0)
+        && (after < 0.0) == (b < 0.0)
+        && a.abs() > before.abs()
+        && b.abs() > after.abs()
+        && (a - b).abs() > (a - before).abs().max((b - after).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// Samples `f` on an `n` by `n` grid spanning the same `-1.0..=1.0` square the output is in.
+    fn sample(n: usize, f: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+        let coord = |i: usize| 2.0 * i as f64 / (n - 1) as f64 - 1.0;
+        (0..n * n).map(|i| f(coord(i % n), coord(i / n))).collect()
+    }
+
+    fn key((x, y): (f64, f64)) -> (i64, i64) {
+        ((x / EPSILON).round() as i64, (y / EPSILON).round() as i64)
+    }
+
+    #[test]
+    fn every_case_of_a_single_cell() {
+        // Corners in grid order with the bit each sets in the case index
+        let bits = [8, 4, 1, 2];
+        let corners: [(f64, f64); 4] = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)];
+        // Edges as the pair of corners they join
+        let edges = [(0, 1), (2, 3), (0, 2), (1, 3)];
+
+        for index in 0..16 {
+            let values: Vec<f64> = bits.iter().map(|bit| if index & bit != 0 { -1.0 } else { 1.0 }).collect();
+            let lines = marching_squares(2, 2, values.clone());
+
+            let expected = match index {
+                0b0000 | 0b1111 => 0,
+                0b0101 | 0b1010 => 2,
+                _ => 1,
+            };
+            assert_eq!(lines.len(), expected, "case {:04b}", index);
+
+            for line in &lines {
+                let on_edges: Vec<_> = line
+                    .iter()
+                    .map(|&(x, y)| {
+                        edges
+                            .iter()
+                            .position(|&(a, b)| {
+                                let ((ax, ay), (bx, by)) = (corners[a], corners[b]);
+                                let on_segment = if ax == bx {
+                                    (x - ax).abs() < EPSILON && y >= ay.min(by) && y <= ay.max(by)
+                                } else {
+                                    (y - ay).abs() < EPSILON && x >= ax.min(bx) && x <= ax.max(bx)
+                                };
+                                on_segment && (values[a] < 0.0) != (values[b] < 0.0)
+                            })
+                            .unwrap_or_else(|| panic!("case {:04b}: ({}, {}) is not on an edge that changes sign", index, x, y))
+                    })
+                    .collect();
+
+                assert_ne!(on_edges[0], on_edges[1], "case {:04b}: segment starts and ends on the same edge", index);
+            }
+        }
+    }
+
+    #[test]
+    fn circle_is_closed() {
+        let n = 41;
+        let r: f64 = 0.51_f64.sqrt();
+        let lines = marching_squares(n, n, sample(n, |x, y| x * x + y * y - r * r));
+        assert!(!lines.is_empty());
+
+        let mut ends = HashMap::new();
+        for point in lines.iter().flatten() {
+            *ends.entry(key(*point)).or_insert(0) += 1;
+
+            let distance = (point.0 * point.0 + point.1 * point.1).sqrt();
+            assert!((distance - r).abs() < 2.0 / (n - 1) as f64, "({}, {}) is far from the circle", point.0, point.1);
+        }
+
+        // Every end of a segment has to be shared with exactly one other segment
+        assert!(ends.values().all(|&count| count == 2), "the contour has loose ends");
+    }
+
+    #[test]
+    fn curve_touching_zero() {
+        let n = 41;
+        let r: f64 = 0.51_f64.sqrt();
+        let lines = marching_squares(n, n, sample(n, |x, y| (x * x + y * y - r * r).powi(2)));
+        assert!(!lines.is_empty());
+
+        for point in lines.iter().flatten() {
+            let distance = (point.0 * point.0 + point.1 * point.1).sqrt();
+            assert!((distance - r).abs() < 2.0 / (n - 1) as f64, "({}, {}) is far from the circle", point.0, point.1);
+        }
+    }
+
+    #[test]
+    fn double_root_of_a_parabola() {
+        let n = 401;
+        let lines = marching_squares(n, n, sample(n, |x, y| (y - x * x).powi(2)));
+        assert!(lines.len() > n / 2, "{} segments", lines.len());
+        assert!(lines.iter().flatten().all(|&(x, y)| (y - x * x).abs() < 2.0 / (n - 1) as f64));
+    }
+
+    #[test]
+    fn near_misses_are_not_curves() {
+        // Stays 0.01 clear of zero, a grid step or more at this size
+        let n = 241;
+        let lines = marching_squares(n, n, sample(n, |x, y| (y - x * x).powi(2) + 1e-4));
+        assert!(lines.is_empty(), "{} segments drawn along a near-miss", lines.len());
+    }
+
+    #[test]
+    fn pole_is_not_a_curve() {
+        let lines = marching_squares(41, 41, sample(41, |x, _| 1.0 / (x - 0.0123)));
+        assert!(lines.is_empty(), "{} segments drawn along the pole", lines.len());
+    }
+
+    #[test]
+    fn invalid_samples_leave_holes() {
+        let n = 41;
+        let lines = marching_squares(n, n, sample(n, |x, y| if x < 0.0 { f64::NAN } else { y - 0.0123 }));
+        assert!(!lines.is_empty());
+        assert!(lines.iter().flatten().all(|&(x, _)| x >= -2.0 / (n - 1) as f64));
+    }
+}
@@ -1,110 +1,336 @@
-use mathsolver::equation::{Equation, Node, ImplStandardOperations};
+use mathsolver::equation::{Equation, ImplStandardOperations};
 use plotters::prelude::*;
 
-use std::error::Error;
+use crate::{GraphSettings, Chart, ChartOn, marching_squares, Chart3D, marching_cubes};
+use crate::field::{BoundEquation, Function, Sample, ScalarField2D, ScalarField3D};
+use crate::marching_cubes::Triangle;
+use crate::calculus::{draw_overlays, shade, Overlays};
+use crate::refine::{refine_lines, refine_triangles};
+use crate::features::{find_features, mark_features};
+use crate::error::GraphError;
+use crate::timings::Timings;
+use crate::report::{Area, MissingLine, Report};
 
-use crate::{GraphSettings, Chart, SubEqual, marching_squares, Chart3D, marching_cubes};
+pub struct Surface {
+    pub triangles: Vec<Triangle>,
+    pub points: Vec<(f64, f64, f64)>,
+    /// The unit normal of each triangle, empty for surfaces contoured from loaded samples, which
+    /// are drawn unshaded.
+    pub normals: Vec<(f64, f64, f64)>,
+    /// How many samples could not be evaluated and were left out as holes.
+    pub invalid: usize,
+}
+
+/// The sampled values of a field over the simulation window, ready to be contoured.
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    /// `NaN` where the field could not be evaluated.
+    pub values: Vec<f64>,
+    pub points: Vec<(f64, f64)>,
+    /// How many samples could not be evaluated and were left out as holes.
+    pub invalid: usize,
+}
+
+pub fn plot<DB: DrawingBackend>(eq: &mut Equation, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    plot_field(&mut BoundEquation::new(eq, settings.params), settings, chart)
+}
+
+pub fn plot_field<F: ScalarField2D, DB: DrawingBackend>(field: &mut F, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    plot_field_timed(field, settings, chart, &mut Timings::default(), &mut Report::default()).map(|_| ())
+}
+
+/// Like `plot_field`, recording how long evaluating, contouring and drawing took and adding the
+/// samples that could not be evaluated to `report`. Returns the contour lines, in the
+/// `-1.0..=1.0` square like `marching_squares` gives them.
+pub fn plot_field_timed<F: ScalarField2D + ?Sized, DB: DrawingBackend>(field: &mut F, settings: &GraphSettings, chart: &mut ChartOn<DB>, timings: &mut Timings, report: &mut Report) -> Result<Vec<[(f64, f64); 2]>, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let grid = timings.record("evaluate", || sample_2d(field, settings));
+    report.invalid += grid.invalid;
+    let mut lines = timings.record("contour", || marching_squares(grid.width, grid.height, grid.values.clone()));
+    timings.record("refine", || refine_lines(field, &grid, settings, &mut lines));
+    timings.record("draw", || draw_contours(&grid, &lines, settings, chart))?;
+    Ok(lines)
+}
 
+pub fn draw_grid<DB: DrawingBackend>(grid: &Grid, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let lines = marching_squares(grid.width, grid.height, grid.values.clone());
+    draw_contours(grid, &lines, settings, chart)
+}
+
+/// Draws the inside points of a grid and the contour lines already found in it.
+pub fn draw_contours<DB: DrawingBackend>(grid: &Grid, lines: &[[(f64, f64); 2]], settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    grid.points.iter().try_for_each(|point| chart.plotting_area().draw_pixel(*point, &RGBAColor(0, 0, 0, 0.4)))?;
 
-pub fn plot(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart) -> Result<(), Box<dyn Error>> {
+    lines.iter().try_for_each(|lines| -> Result<(), GraphError> {
+        chart.draw_series(
+            LineSeries::new(
+                lines.iter().map(|&point| to_window(settings, point)),
+                BLACK.stroke_width(2)
+            )
+        )
+        .map(|_| ())
+        .map_err(GraphError::from)
+    })?;
+
+    Ok(())
+}
+
+/// How many samples `sample_2d` takes across and down the window.
+pub fn grid_size(settings: &GraphSettings) -> (usize, usize) {
+    (settings.image_width as usize * 2 + 1, settings.image_height as usize * 2 + 1)
+}
+
+pub fn sample_2d<F: ScalarField2D + ?Sized>(field: &mut F, settings: &GraphSettings) -> Grid {
     
     let fidelity_w = settings.image_width as usize;
     let fidelity_h = settings.image_height as usize;
     
     let transform_x = |x: i32| -> f64 {
-        ((x as f64) / fidelity_w as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0
+        (settings.sim_window.0+settings.sim_window.1) / 2.0 + ((x as f64) / fidelity_w as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0
     };
     
     let transform_y = |y: i32| -> f64 {
-        ((y as f64) / fidelity_h as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0
+        (settings.sim_window.2+settings.sim_window.3) / 2.0 + ((y as f64) / fidelity_h as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0
     };
 
     let mut value_grid = vec![0.0; (fidelity_w*2+1)*(fidelity_h*2+1)];
+    let mut points = Vec::new();
+    let mut invalid = 0;
 
     let mut total_index = 0;
 
+    // Whole rows are evaluated at once, the same x values for every row
+    let xs: Vec<f64> = (-(fidelity_w as i32)..=fidelity_w as i32).map(transform_x).collect();
+    let mut row = vec![Sample::Invalid; xs.len()];
+
     for j in -(fidelity_h as i32)..=fidelity_h as i32 {
         let y = transform_y(j);
-        for i in -(fidelity_w as i32)..=fidelity_w as i32 {
-            let x = transform_x(i);
-            match eq.call_on_custom::<SubEqual>(&[("x", x), ("y", y)]) {
-                Node::Bool(val) => {
+        field.evaluate_row(&xs, y, &mut row);
+        for (&x, &sample) in xs.iter().zip(&row) {
+            match sample {
+                Sample::Inside(val) => {
                     if val {
-                        chart.plotting_area().draw_pixel((x, y), &RGBAColor(0, 0, 0, 0.4))?;
+                        points.push((x, y));
                     }
                 },
-                Node::Real(val) => {
+                Sample::Value(val) => {
                     value_grid[total_index] = val;
                     total_index += 1;
+                },
+                Sample::Invalid => {
+                    value_grid[total_index] = f64::NAN;
+                    total_index += 1;
+                    invalid += 1;
                 }
-                _ => panic!()
             }
         }
     }
 
-    let lines = marching_squares(fidelity_w * 2 + 1, fidelity_h * 2 + 1, value_grid);
+    let (width, height) = grid_size(settings);
+    Grid {
+        width,
+        height,
+        values: value_grid,
+        points,
+        invalid,
+    }
+}
 
-    lines.into_iter().try_for_each(|lines| -> Result<(), Box<dyn Error>> {
-        chart.draw_series(
-            LineSeries::new(
-                lines,
-                BLACK.stroke_width(2)
-            )
-        )
-        .map(|_| ())
-        .map_err(|e| Box::new(e) as Box<dyn Error>)
-    })?;
+/// Maps a point from the `-1.0..=1.0` square the contouring works in onto the simulation window.
+pub fn to_window(settings: &GraphSettings, (x, y): (f64, f64)) -> (f64, f64) {
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    (xmin + (x + 1.0) / 2.0 * (xmax - xmin), ymin + (y + 1.0) / 2.0 * (ymax - ymin))
+}
 
-    Ok(())
+/// The inverse of `to_window`.
+pub fn from_window(settings: &GraphSettings, (x, y): (f64, f64)) -> (f64, f64) {
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    ((x - xmin) / (xmax - xmin) * 2.0 - 1.0, (y - ymin) / (ymax - ymin) * 2.0 - 1.0)
+}
 
+/// Maps a point from the `-1.0..=1.0` cube the contouring works in onto the simulation window, z
+/// spanning the same range as x.
+pub fn to_window_3d(settings: &GraphSettings, (x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    (xmin + (x + 1.0) / 2.0 * (xmax - xmin), ymin + (y + 1.0) / 2.0 * (ymax - ymin), xmin + (z + 1.0) / 2.0 * (xmax - xmin))
 }
 
-pub fn plot_x(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart) -> Result<(), Box<dyn Error>> {
+/// The x values functions of x are sampled at.
+pub fn sample_xs(settings: &GraphSettings) -> Vec<f64> {
     let fidelity = settings.image_width / 8;
+    (-(fidelity as i32)..=fidelity as i32)
+        .map(|i| (settings.sim_window.0+settings.sim_window.1) / 2.0 + ((i as f64) / fidelity as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0)
+        .collect()
+}
 
-    chart.draw_series(LineSeries::new(
-        (-(fidelity as i32)..=fidelity as i32)
-            .map(|i| ((i as f64) / fidelity as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0)
-            .map(|x| {
-                (x, eq.call_on(&[("x", x)]).as_f64().unwrap())
-            }),
-        BLACK.stroke_width(2),
-    ))?;
+/// Plots an equation in x alone as `y = f(x)` with `mathsolver`, leaving gaps where it has no
+/// number, and draws the overlays on top of it like `plot_function`.
+pub fn plot_x<DB: DrawingBackend>(eq: &mut Equation, overlays: &Overlays, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<Vec<MissingLine>, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let mut bindings = [&[("x", 0.0)][..], settings.params].concat();
+    let mut function = |x: f64| {
+        bindings[0].1 = x;
+        eq.call_on(&bindings).as_f64().unwrap_or(f64::NAN)
+    };
+
+    plot_function(&mut function, overlays, settings, chart)
+}
+
+/// Plots `y = f(x)` at the x values of `sample_xs` and draws the overlays on top of it. Returns
+/// the tangents and normals the function has no value or slope for.
+pub fn plot_function<F: Function + ?Sized, DB: DrawingBackend>(function: &mut F, overlays: &Overlays, settings: &GraphSettings, chart: &mut ChartOn<DB>) -> Result<Vec<MissingLine>, GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    let xs = sample_xs(settings);
+    let points: Vec<_> = xs.iter().map(|&x| (x, function.evaluate(x))).collect();
+    draw_function(&points, BLACK.stroke_width(2), chart)?;
+    draw_overlays(function, &xs, overlays, settings, chart)
+}
+
+/// Plots named functions of x with their overlays and notable points, then shades the areas asked
+/// for. The notable points, the sizes of the areas and the lines that couldn't be drawn are added
+/// to `report`.
+pub fn plot_functions<DB: DrawingBackend>(functions: &mut [(&str, Box<dyn Function + '_>)], overlays: &Overlays, settings: &GraphSettings, chart: &mut ChartOn<DB>, report: &mut Report) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    for (name, function) in functions.iter_mut() {
+        let missing = plot_function(function.as_mut(), overlays, settings, chart)?;
+        report.missing_lines.extend(missing.into_iter().map(|line| (name.to_string(), line)));
+
+        if overlays.features {
+            let features = find_features(function.as_mut(), settings);
+            mark_features(&features, chart)?;
+            report.features.push((name.to_string(), features));
+        }
+    }
+
+    let span = match overlays.shade {
+        Some(span) => span,
+        None => return Ok(()),
+    };
+
+    if overlays.shade_between {
+        match functions {
+            [(upper_name, upper), (lower_name, lower), ..] => {
+                let area = shade(upper.as_mut(), Some(lower.as_mut()), span, settings, chart)?;
+                report.areas.push(Area { upper: upper_name.to_string(), lower: Some(lower_name.to_string()), span, area });
+            }
+            _ => return Err(GraphError::BadArgument("shading between curves needs two equations".to_string())),
+        }
+    } else {
+        for (name, function) in functions.iter_mut() {
+            let area = shade(function.as_mut(), None, span, settings, chart)?;
+            report.areas.push(Area { upper: name.to_string(), lower: None, span, area });
+        }
+    }
 
     Ok(())
 }
 
-pub fn plot_y(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart) -> Result<(), Box<dyn Error>> {
+/// Draws the points of a function as lines, leaving gaps where it isn't defined.
+pub fn draw_function<DB: DrawingBackend>(points: &[(f64, f64)], style: ShapeStyle, chart: &mut ChartOn<DB>) -> Result<(), GraphError>
+where
+    DB::ErrorType: 'static,
+{
+    for run in points.split(|(_, y)| !y.is_finite()).filter(|run| run.len() > 1) {
+        chart.draw_series(LineSeries::new(run.iter().copied(), style))?;
+    }
+
+    Ok(())
+}
+
+pub fn plot_y(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart) -> Result<(), GraphError> {
     let fidelity = settings.image_height / 8;
+    let mut bindings = [&[("y", 0.0)][..], settings.params].concat();
+
+    let points = (-(fidelity as i32)..=fidelity as i32)
+        .map(|i| (settings.sim_window.2+settings.sim_window.3) / 2.0 + ((i as f64) / fidelity as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0)
+        .map(|y| {
+            bindings[0].1 = y;
+            eq.call_on(&bindings)
+                .as_f64()
+                .map(|x| (x, y))
+                .ok_or_else(|| GraphError::Evaluation(format!("no number for y = {}", y)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    chart.draw_series(LineSeries::new(points, BLACK.stroke_width(2)))?;
+
+    Ok(())
+}
+
+pub fn plot_3d(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart3D) -> Result<(), GraphError> {
+    plot_field_3d(&mut BoundEquation::new(eq, settings.params), settings, chart)
+}
+
+pub fn plot_field_3d<F: ScalarField3D>(field: &mut F, settings: &GraphSettings, chart: &mut Chart3D) -> Result<(), GraphError> {
+    plot_field_3d_timed(field, settings, chart, &mut Timings::default(), &mut Report::default())
+}
+
+/// Like `plot_field_3d`, recording how long evaluating, contouring and drawing took and adding
+/// the samples that could not be evaluated to `report`.
+pub fn plot_field_3d_timed<F: ScalarField3D>(field: &mut F, settings: &GraphSettings, chart: &mut Chart3D, timings: &mut Timings, report: &mut Report) -> Result<(), GraphError> {
+    let surface = surface_3d_timed(field, settings, timings);
+    report.invalid += surface.invalid;
+    timings.record("draw", || draw_surface(&surface, chart))
+}
+
+/// The direction light falls from when shading surfaces.
+const LIGHT: (f64, f64, f64) = (0.36, 0.8, 0.48);
 
-    chart.draw_series(LineSeries::new(
-        (-(fidelity as i32)..=fidelity as i32)
-            .map(|i| ((i as f64) / fidelity as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0)
-            .map(|y| {
-                (eq.call_on(&[("y", y)]).as_f64().unwrap(), y)
-            }),
-        BLACK.stroke_width(2),
-    ))?;
+pub fn draw_surface(surface: &Surface, chart: &mut Chart3D) -> Result<(), GraphError> {
+    surface.points.iter().try_for_each(|point| chart.plotting_area().draw_pixel(*point, &RGBAColor(0, 0, 0, 0.4)))?;
+
+    // Both sides of the surface are lit the same, since either can face the viewer
+    chart.draw_series(surface.triangles.iter().zip(&surface.normals).map(|(triangle, normal)| {
+        let light = (normal.0 * LIGHT.0 + normal.1 * LIGHT.1 + normal.2 * LIGHT.2).abs();
+        let shade = (60.0 + 170.0 * light) as u8;
+        Polygon::new(triangle.to_vec(), RGBAColor(shade, shade, shade, 0.6).filled())
+    }))?;
+
+    chart.draw_series(
+        surface.triangles.iter().map(|triangle| Polygon::new(triangle.to_vec(), RGBAColor(0, 0, 0, 0.4).stroke_width(2))),
+    )?;
 
     Ok(())
 }
 
-pub fn plot_3d(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart3D) -> Result<(), Box<dyn Error>> {
+pub fn surface_3d<F: ScalarField3D>(field: &mut F, settings: &GraphSettings) -> Surface {
+    surface_3d_timed(field, settings, &mut Timings::default())
+}
+
+/// Like `surface_3d`, recording how long evaluating and contouring took.
+pub fn surface_3d_timed<F: ScalarField3D>(field: &mut F, settings: &GraphSettings, timings: &mut Timings) -> Surface {
     let fidelity_w = settings.image_width as usize / 20;
     let fidelity_h = settings.image_height as usize / 20;
     
     let transform_x = |x: i32| -> f64 {
-        ((x as f64) / fidelity_w as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0
+        (settings.sim_window.0+settings.sim_window.1) / 2.0 + ((x as f64) / fidelity_w as f64) * (settings.sim_window.1-settings.sim_window.0) / 2.0
     };
     
     let transform_y = |y: i32| -> f64 {
-        ((y as f64) / fidelity_h as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0
+        (settings.sim_window.2+settings.sim_window.3) / 2.0 + ((y as f64) / fidelity_h as f64) * (settings.sim_window.3-settings.sim_window.2) / 2.0
     };
 
-    let mut value_grid = vec![0.0; (fidelity_w*2+1)*(fidelity_w*2+1)*(fidelity_h*2+1)];
-
-    let mut total_index = 0;
-
     // (-(fidelity_w as i32)..=fidelity_w as i32).into_par_iter().map(|l| {
     //     let z = transform_x(l);
     //     (-(fidelity_h as i32)..=fidelity_h as i32).map(|j| {
@@ -130,34 +356,56 @@ pub fn plot_3d(eq: &mut Equation, settings: &GraphSettings, chart: &mut Chart3D)
     //     })
     // });
 
-    for l in -(fidelity_w as i32)..=fidelity_w as i32 {
-        let z = transform_x(l);
-        for j in -(fidelity_h as i32)..=fidelity_h as i32 {
-            let y = transform_y(j);
-            for i in -(fidelity_w as i32)..=fidelity_w as i32 {
-                let x = transform_x(i);
-                match eq.call_on_custom::<SubEqual>(&[("x", x), ("y", y), ("z", z)]) {
-                    Node::Bool(val) => {
-                        if val {
-                            chart.plotting_area().draw_pixel((x, y, z), &RGBAColor(0, 0, 0, 0.4))?;
+    let (value_grid, points, invalid) = timings.record("evaluate", || {
+        let mut value_grid = vec![0.0; (fidelity_w*2+1)*(fidelity_w*2+1)*(fidelity_h*2+1)];
+        let mut points = Vec::new();
+        let mut invalid = 0;
+
+        let mut total_index = 0;
+
+        let xs: Vec<f64> = (-(fidelity_w as i32)..=fidelity_w as i32).map(transform_x).collect();
+        let mut row = vec![Sample::Invalid; xs.len()];
+
+        for l in -(fidelity_w as i32)..=fidelity_w as i32 {
+            let z = transform_x(l);
+            for j in -(fidelity_h as i32)..=fidelity_h as i32 {
+                let y = transform_y(j);
+                field.evaluate_row(&xs, y, z, &mut row);
+                for (&x, &sample) in xs.iter().zip(&row) {
+                    match sample {
+                        Sample::Inside(val) => {
+                            if val {
+                                points.push((x, y, z));
+                            }
+                        },
+                        Sample::Value(val) => {
+                            value_grid[total_index] = val;
+                            total_index += 1;
+                        },
+                        Sample::Invalid => {
+                            value_grid[total_index] = f64::NAN;
+                            total_index += 1;
+                            invalid += 1;
                         }
-                    },
-                    Node::Real(val) => {
-                        value_grid[total_index] = val;
-                        total_index += 1;
                     }
-                    _ => panic!()
                 }
             }
         }
-    }
 
-    let triangles = marching_cubes(fidelity_w * 2 + 1, fidelity_h * 2 + 1, fidelity_w * 2 + 1, value_grid);
+        (value_grid, points, invalid)
+    });
 
-    chart.draw_series(
-        triangles.into_iter().map(|triangle| Polygon::new(triangle, RGBAColor(0, 0, 0, 0.4).stroke_width(2))),
-    )?;
+    let triangles: Vec<Triangle> = timings.record("contour", || {
+        marching_cubes(fidelity_w * 2 + 1, fidelity_h * 2 + 1, fidelity_w * 2 + 1, value_grid)
+            .into_iter()
+            .map(|triangle| triangle.map(|point| to_window_3d(settings, point)))
+            .collect()
+    });
 
-    Ok(())
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    let (dx, dy) = ((xmax - xmin) / (fidelity_w * 2) as f64, (ymax - ymin) / (fidelity_h * 2) as f64);
+    let cell = (2.0 * dx * dx + dy * dy).sqrt();
+    let (triangles, normals) = timings.record("refine", || refine_triangles(field, settings, triangles, cell));
 
+    Surface { triangles, points, normals, invalid }
 }
@@ -0,0 +1,270 @@
+use crate::GraphSettings;
+use crate::field::{Sample, ScalarField2D, ScalarField3D};
+use crate::marching_cubes::Triangle;
+use crate::plot::{from_window, to_window, Grid};
+
+/// How close vertices are brought to the curve or surface unless told otherwise, as the length
+/// of the last Newton step in the units of the window.
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// The most Newton steps taken for one vertex. Simple roots converge in two or three, this only
+/// matters where the gradient vanishes on the curve and convergence is linear.
+const MAX_STEPS: usize = 8;
+
+/// The step of the central differences used for fields that don't know their gradient, as a
+/// fraction of a grid cell.
+const DIFFERENCE_STEP: f64 = 1e-3;
+
+/// Moves the ends of the contour segments onto the curve with Newton steps. Marching squares only
+/// interpolates linearly between samples, so this makes curves accurate without sampling more
+/// finely. Does nothing if `settings.refine` is `None`.
+pub fn refine_lines<F: ScalarField2D + ?Sized>(field: &mut F, grid: &Grid, settings: &GraphSettings, lines: &mut [[(f64, f64); 2]]) {
+    let tolerance = match settings.refine {
+        Some(tolerance) => tolerance,
+        None => return,
+    };
+
+    let (xmin, xmax, ymin, ymax) = settings.sim_window;
+    let cell = ((xmax - xmin) / (grid.width - 1) as f64).hypot((ymax - ymin) / (grid.height - 1) as f64);
+
+    for point in lines.iter_mut().flatten() {
+        if let Some(refined) = newton_2d(field, to_window(settings, *point), tolerance, cell) {
+            *point = from_window(settings, refined);
+        }
+    }
+}
+
+/// Moves the corners of the triangles onto the surface like `refine_lines` if `settings.refine`
+/// is set, and finds the normal of each triangle from the gradient at its centre either way.
+/// `cell` is the diagonal of a grid cell.
+pub fn refine_triangles<F: ScalarField3D>(field: &mut F, settings: &GraphSettings, mut triangles: Vec<Triangle>, cell: f64) -> (Vec<Triangle>, Vec<(f64, f64, f64)>) {
+    if let Some(tolerance) = settings.refine {
+        for point in triangles.iter_mut().flatten() {
+            if let Some(refined) = newton_3d(field, *point, tolerance, cell) {
+                *point = refined;
+            }
+        }
+    }
+
+    let normals = triangles
+        .iter()
+        .map(|triangle| {
+            let centre = (
+                (triangle[0].0 + triangle[1].0 + triangle[2].0) / 3.0,
+                (triangle[0].1 + triangle[1].1 + triangle[2].1) / 3.0,
+                (triangle[0].2 + triangle[1].2 + triangle[2].2) / 3.0,
+            );
+            let (gx, gy, gz) = gradient_3d(field, centre, cell * DIFFERENCE_STEP).unwrap_or((0.0, 0.0, 0.0));
+            let length = (gx * gx + gy * gy + gz * gz).sqrt();
+            if length > 0.0 && length.is_finite() { (gx / length, gy / length, gz / length) } else { (0.0, 0.0, 0.0) }
+        })
+        .collect();
+
+    (triangles, normals)
+}
+
+/// Projects a point onto `f = 0`, stopping once a step is shorter than `tolerance`. `None` if the
+/// field can't be evaluated on the way or the point would move further than `max_distance`,
+/// which happens near poles and where there is no root nearby to converge to.
+fn newton_2d<F: ScalarField2D + ?Sized>(field: &mut F, start: (f64, f64), tolerance: f64, max_distance: f64) -> Option<(f64, f64)> {
+    let (mut x, mut y) = start;
+
+    for _ in 0..MAX_STEPS {
+        let f = value_2d(field, x, y)?;
+        if f == 0.0 {
+            break;
+        }
+
+        let (gx, gy) = gradient_2d(field, (x, y), max_distance * DIFFERENCE_STEP)?;
+        let length_squared = gx * gx + gy * gy;
+        if length_squared <= 0.0 || !length_squared.is_finite() {
+            return None;
+        }
+
+        let (dx, dy) = (f * gx / length_squared, f * gy / length_squared);
+        x -= dx;
+        y -= dy;
+        if dx.hypot(dy) < tolerance {
+            break;
+        }
+    }
+
+    ((x - start.0).hypot(y - start.1) <= max_distance).then_some((x, y))
+}
+
+/// Moves a point to where both `a = 0` and `b = 0` with Newton steps on the system of the two,
+/// like `newton_2d`. `None` also where the curves touch without crossing, since the system is
+/// singular there.
+pub fn newton_system<A: ScalarField2D + ?Sized, B: ScalarField2D + ?Sized>(a: &mut A, b: &mut B, start: (f64, f64), tolerance: f64, max_distance: f64) -> Option<(f64, f64)> {
+    let h = max_distance * DIFFERENCE_STEP;
+    let (mut x, mut y) = start;
+
+    for _ in 0..MAX_STEPS {
+        let (fa, fb) = (value_2d(a, x, y)?, value_2d(b, x, y)?);
+        if fa == 0.0 && fb == 0.0 {
+            break;
+        }
+
+        let (ax, ay) = gradient_2d(a, (x, y), h)?;
+        let (bx, by) = gradient_2d(b, (x, y), h)?;
+        let determinant = ax * by - ay * bx;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+
+        let (dx, dy) = ((fa * by - fb * ay) / determinant, (ax * fb - bx * fa) / determinant);
+        x -= dx;
+        y -= dy;
+        if dx.hypot(dy) < tolerance {
+            break;
+        }
+    }
+
+    ((x - start.0).hypot(y - start.1) <= max_distance).then_some((x, y))
+}
+
+/// Projects a point onto `f = 0` in 3D, like `newton_2d`.
+fn newton_3d<F: ScalarField3D>(field: &mut F, start: (f64, f64, f64), tolerance: f64, max_distance: f64) -> Option<(f64, f64, f64)> {
+    let (mut x, mut y, mut z) = start;
+
+    for _ in 0..MAX_STEPS {
+        let f = value_3d(field, x, y, z)?;
+        if f == 0.0 {
+            break;
+        }
+
+        let (gx, gy, gz) = gradient_3d(field, (x, y, z), max_distance * DIFFERENCE_STEP)?;
+        let length_squared = gx * gx + gy * gy + gz * gz;
+        if length_squared <= 0.0 || !length_squared.is_finite() {
+            return None;
+        }
+
+        let (dx, dy, dz) = (f * gx / length_squared, f * gy / length_squared, f * gz / length_squared);
+        x -= dx;
+        y -= dy;
+        z -= dz;
+        if (dx * dx + dy * dy + dz * dz).sqrt() < tolerance {
+            break;
+        }
+    }
+
+    let (dx, dy, dz) = (x - start.0, y - start.1, z - start.2);
+    ((dx * dx + dy * dy + dz * dz).sqrt() <= max_distance).then_some((x, y, z))
+}
+
+fn value_2d<F: ScalarField2D + ?Sized>(field: &mut F, x: f64, y: f64) -> Option<f64> {
+    match field.evaluate(x, y) {
+        Sample::Value(val) => Some(val),
+        _ => None,
+    }
+}
+
+fn value_3d<F: ScalarField3D>(field: &mut F, x: f64, y: f64, z: f64) -> Option<f64> {
+    match field.evaluate(x, y, z) {
+        Sample::Value(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// The exact gradient if the field knows it, otherwise central differences with step `h`.
+fn gradient_2d<F: ScalarField2D + ?Sized>(field: &mut F, (x, y): (f64, f64), h: f64) -> Option<(f64, f64)> {
+    if let Some(gradient) = field.gradient(x, y) {
+        return Some(gradient);
+    }
+
+    Some((
+        (value_2d(field, x + h, y)? - value_2d(field, x - h, y)?) / (2.0 * h),
+        (value_2d(field, x, y + h)? - value_2d(field, x, y - h)?) / (2.0 * h),
+    ))
+}
+
+/// The exact gradient if the field knows it, otherwise central differences with step `h`.
+fn gradient_3d<F: ScalarField3D>(field: &mut F, (x, y, z): (f64, f64, f64), h: f64) -> Option<(f64, f64, f64)> {
+    if let Some(gradient) = field.gradient(x, y, z) {
+        return Some(gradient);
+    }
+
+    Some((
+        (value_3d(field, x + h, y, z)? - value_3d(field, x - h, y, z)?) / (2.0 * h),
+        (value_3d(field, x, y + h, z)? - value_3d(field, x, y - h, z)?) / (2.0 * h),
+        (value_3d(field, x, y, z + h)? - value_3d(field, x, y, z - h)?) / (2.0 * h),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::CompiledField;
+    use crate::marching_squares;
+    use crate::plot::{sample_2d, surface_3d};
+
+    fn settings(size: u32, refine: Option<f64>) -> GraphSettings<'static> {
+        GraphSettings { path: "", image_width: size, image_height: size, sim_window: (-1.0, 1.0, -1.0, 1.0), yaw: 0.5, params: &[], refine }
+    }
+
+    /// The largest `|x^2 + y^2 - 0.5|` over the vertices of the contoured and refined circle.
+    fn circle_error<F: ScalarField2D>(field: &mut F, settings: &GraphSettings) -> f64 {
+        let grid = sample_2d(field, settings);
+        let mut lines = marching_squares(grid.width, grid.height, grid.values.clone());
+        refine_lines(field, &grid, settings, &mut lines);
+
+        assert!(!lines.is_empty());
+        lines.iter().flatten().map(|point| {
+            let (x, y) = to_window(settings, *point);
+            (x * x + y * y - 0.5).abs()
+        })
+        .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn exact_gradients_put_vertices_on_the_curve() {
+        let mut field = CompiledField::parse("x^2+y^2=0.5", &[]).unwrap();
+        assert!(circle_error(&mut field, &settings(10, Some(DEFAULT_TOLERANCE))) < 1e-9);
+    }
+
+    #[test]
+    fn numeric_gradients_put_vertices_on_the_curve() {
+        let mut field = |x: f64, y: f64| x * x + y * y - 0.5;
+        assert!(circle_error(&mut field, &settings(10, Some(DEFAULT_TOLERANCE))) < 1e-9);
+    }
+
+    #[test]
+    fn tolerance_bounds_the_error() {
+        let mut field = |x: f64, y: f64| x * x + y * y - 0.5;
+        // Within the tolerance of the curve, so off by at most the tolerance times |∇f| = 2r
+        let bound = 1e-3 * 2.0 * 0.5_f64.sqrt();
+        assert!(circle_error(&mut field, &settings(10, None)) > bound);
+        assert!(circle_error(&mut field, &settings(10, Some(1e-3))) <= bound);
+    }
+
+    #[test]
+    fn refined_surface_has_outward_normals() {
+        // 3D plots sample every 20 pixels
+        let settings = settings(200, Some(DEFAULT_TOLERANCE));
+        let mut field = CompiledField::parse("x^2+y^2+z^2=0.5", &[]).unwrap();
+        let surface = surface_3d(&mut field, &settings);
+
+        assert_eq!(surface.normals.len(), surface.triangles.len());
+        for (triangle, normal) in surface.triangles.iter().zip(&surface.normals) {
+            for (x, y, z) in triangle {
+                assert!((x * x + y * y + z * z - 0.5).abs() < 1e-9);
+            }
+            let (x, y, z) = triangle[0];
+            assert!(x * normal.0 + y * normal.1 + z * normal.2 > 0.0);
+        }
+    }
+
+    #[test]
+    fn unrefined_surfaces_are_shaded() {
+        // Without an exact gradient, so that the normals come from differences
+        let settings = settings(200, None);
+        let surface = surface_3d(&mut |x: f64, y: f64, z: f64| x * x + y * y + z * z - 0.5, &settings);
+
+        assert!(!surface.triangles.is_empty());
+        assert_eq!(surface.normals.len(), surface.triangles.len());
+        for (triangle, normal) in surface.triangles.iter().zip(&surface.normals) {
+            let (x, y, z) = triangle[0];
+            assert!(x * normal.0 + y * normal.1 + z * normal.2 > 0.0);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::calculus::Span;
+use crate::features::Features;
+
+/// What rendering found out about the equations besides the image, for the caller to print.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The notable points of each explicit function, by its equation.
+    pub features: Vec<(String, Features)>,
+    pub areas: Vec<Area>,
+    pub intersections: Vec<Intersection>,
+    /// How many samples, over all the equations, could not be evaluated and were left out of the
+    /// image as holes.
+    pub invalid: usize,
+    /// The tangent and normal lines that could not be drawn, by the equation of their function.
+    pub missing_lines: Vec<(String, MissingLine)>,
+}
+
+/// The signed area under a function, or between two of them, over a span.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Area {
+    pub upper: String,
+    /// The function the area reaches down to, the x-axis if there is none.
+    pub lower: Option<String>,
+    pub span: Span,
+    pub area: f64,
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.lower {
+            Some(lower) => write!(f, "area between {} and {} from {} to {}: {}", self.upper, lower, self.span.start, self.span.end, self.area),
+            None => write!(f, "area under {} from {} to {}: {}", self.upper, self.span.start, self.span.end, self.area),
+        }
+    }
+}
+
+/// A point where the curves of two equations cross.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Intersection {
+    pub a: String,
+    pub b: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+impl fmt::Display for Intersection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} and {} meet at ({}, {})", self.a, self.b, self.x, self.y)
+    }
+}
+
+/// A tangent or normal line left out since the function has no value or slope where it was asked
+/// for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingLine {
+    pub x: f64,
+    /// Whether it is the normal rather than the tangent.
+    pub normal: bool,
+}
+
+impl fmt::Display for MissingLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no {} at x = {}", if self.normal { "normal" } else { "tangent" }, self.x)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, features) in &self.features {
+            writeln!(f, "{}\n{}", name, features)?;
+        }
+        for area in &self.areas {
+            writeln!(f, "{}", area)?;
+        }
+        for intersection in &self.intersections {
+            writeln!(f, "{}", intersection)?;
+        }
+        Ok(())
+    }
+}
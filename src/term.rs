@@ -0,0 +1,220 @@
+use mathsolver::equation::Equation;
+use plotters::prelude::*;
+use plotters_backend::{BackendColor, BackendCoord, BackendTextStyle, DrawingErrorKind};
+use plotters_backend::text_anchor::{HPos, VPos};
+
+use std::env;
+use std::io::{self, Write};
+
+use crate::{GraphSettings, create_graph};
+use crate::plot::plot;
+use crate::error::GraphError;
+
+/// Every character cell is a 2x4 block of braille dots.
+const CELL_WIDTH: u32 = 2;
+const CELL_HEIGHT: u32 = 4;
+
+/// Bits of the braille pattern for each dot in a cell, indexed by `[y][x]`.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// A backend that rasterises into braille dots and writes the result to `out`, stdout for the
+/// command line tool. Text is kept as plain characters on top of the dots instead of being rasterised.
+pub struct TermBackend<W: Write> {
+    out: W,
+    columns: u32,
+    rows: u32,
+    cells: Vec<u32>,
+    text: Vec<Option<char>>,
+}
+
+impl<W: Write> TermBackend<W> {
+    pub fn new(out: W, columns: u32, rows: u32) -> TermBackend<W> {
+        TermBackend {
+            out,
+            columns,
+            rows,
+            cells: vec![0; (columns * rows) as usize],
+            text: vec![None; (columns * rows) as usize],
+        }
+    }
+}
+
+impl TermBackend<io::Stdout> {
+    /// Uses the size of the terminal as reported by `COLUMNS` and `LINES`, falling back to 100x40.
+    pub fn from_env() -> TermBackend<io::Stdout> {
+        let read = |name: &str, default: u32| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        // Leave a line for the prompt
+        TermBackend::new(io::stdout(), read("COLUMNS", 100), read("LINES", 41).saturating_sub(1).max(1))
+    }
+}
+
+impl<W: Write> DrawingBackend for TermBackend<W> {
+    type ErrorType = io::Error;
+
+    fn get_size(&self) -> (u32, u32) {
+        (self.columns * CELL_WIDTH, self.rows * CELL_HEIGHT)
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        let mut out = String::new();
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let index = (column + row * self.columns) as usize;
+                out.push(match self.text[index] {
+                    Some(c) => c,
+                    None => char::from_u32(0x2800 + self.cells[index]).unwrap_or(' '),
+                });
+            }
+            out.push('\n');
+        }
+
+        self.out.write_all(out.as_bytes()).map_err(DrawingErrorKind::DrawingError)
+    }
+
+    fn draw_pixel(&mut self, (x, y): BackendCoord, color: BackendColor) -> Result<(), DrawingErrorKind<io::Error>> {
+        let (width, height) = self.get_size();
+        if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+            return Ok(());
+        }
+
+        // Anything that is dark enough once blended onto the white background gets a dot, which
+        // keeps the axes and curves but drops the faint mesh lines
+        let (r, g, b) = color.rgb;
+        let luminance = (0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64) / 255.0;
+        if color.alpha * (1.0 - luminance) < 0.3 {
+            return Ok(());
+        }
+
+        let (x, y) = (x as u32, y as u32);
+        let index = (x / CELL_WIDTH + (y / CELL_HEIGHT) * self.columns) as usize;
+        self.cells[index] |= BRAILLE_DOTS[(y % CELL_HEIGHT) as usize][(x % CELL_WIDTH) as usize];
+
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(&mut self, text: &str, style: &TStyle, (x, y): BackendCoord) -> Result<(), DrawingErrorKind<io::Error>> {
+        let length = text.chars().count() as i32;
+        let anchor = style.anchor();
+
+        let column = x / CELL_WIDTH as i32 - match anchor.h_pos {
+            HPos::Left => 0,
+            HPos::Center => length / 2,
+            HPos::Right => length,
+        };
+        let row = y / CELL_HEIGHT as i32 - match anchor.v_pos {
+            VPos::Top | VPos::Center => 0,
+            VPos::Bottom => 1,
+        };
+
+        if row < 0 || row as u32 >= self.rows {
+            return Ok(());
+        }
+
+        for (offset, c) in text.chars().enumerate() {
+            let column = column + offset as i32;
+            if column >= 0 && (column as u32) < self.columns {
+                self.text[(column as u32 + row as u32 * self.columns) as usize] = Some(c);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn estimate_text_size<TStyle: BackendTextStyle>(&self, text: &str, _style: &TStyle) -> Result<(u32, u32), DrawingErrorKind<io::Error>> {
+        Ok((text.chars().count() as u32 * CELL_WIDTH, CELL_HEIGHT))
+    }
+}
+
+/// Plots the equations into the terminal. The sampling resolution follows the size of the
+/// terminal rather than the image size in `settings`.
+pub fn plot_term(eqs: &mut [Equation], settings: &GraphSettings) -> Result<(), GraphError> {
+    let backend = TermBackend::from_env();
+    let (image_width, image_height) = backend.get_size();
+    let settings = GraphSettings {
+        image_width,
+        image_height,
+        ..*settings
+    };
+
+    let root = backend.into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut graph = create_graph(&settings, &root)?;
+    for eq in eqs.iter_mut() {
+        plot(eq, &settings, &mut graph)?;
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(columns: u32, rows: u32, draw: impl FnOnce(&mut TermBackend<&mut Vec<u8>>)) -> String {
+        let mut out = Vec::new();
+        let mut backend = TermBackend::new(&mut out, columns, rows);
+        draw(&mut backend);
+        backend.present().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn packs_dots_into_braille_cells() {
+        let dot = |x, y| render(1, 1, |backend| backend.draw_pixel((x, y), BLACK.to_backend_color()).unwrap());
+        assert_eq!(dot(0, 0), "\u{2801}\n");
+        assert_eq!(dot(0, 3), "\u{2840}\n");
+        assert_eq!(dot(1, 0), "\u{2808}\n");
+        assert_eq!(dot(1, 3), "\u{2880}\n");
+
+        let full = render(2, 1, |backend| {
+            for (x, y) in (0..2).flat_map(|x| (0..4).map(move |y| (x, y))) {
+                backend.draw_pixel((x, y), BLACK.to_backend_color()).unwrap();
+            }
+        });
+        assert_eq!(full, "\u{28ff}\u{2800}\n");
+    }
+
+    #[test]
+    fn clips_to_the_bounds() {
+        let output = render(2, 1, |backend| {
+            for (x, y) in [(-1, 0), (4, 0), (0, -1), (0, 4)] {
+                backend.draw_pixel((x, y), BLACK.to_backend_color()).unwrap();
+            }
+            let style = TextStyle::from(("sans-serif", 10).into_font());
+            backend.draw_text("abc", &style, (2, 0)).unwrap();
+            backend.draw_text("below", &style, (0, 4)).unwrap();
+        });
+        assert_eq!(output, "\u{2800}a\n");
+    }
+
+    #[test]
+    fn renders_a_chart() {
+        let mut out = Vec::new();
+        {
+            let root = TermBackend::new(&mut out, 4, 2).into_drawing_area();
+            root.fill(&WHITE).unwrap();
+            let mut chart = ChartBuilder::on(&root).build_cartesian_2d(0.0..1.0, 0.0..1.0).unwrap();
+            // Light colours are dropped, so only the diagonal shows
+            chart.configure_mesh().light_line_style(RGBColor(230, 230, 230)).bold_line_style(RGBColor(230, 230, 230)).draw().unwrap();
+            chart.draw_series(LineSeries::new([(0.0, 0.0), (1.0, 1.0)], BLACK)).unwrap();
+            root.present().unwrap();
+        }
+
+        let output = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<char>> = output.lines().map(|line| line.chars().collect()).collect();
+        let empty = '\u{2800}';
+        assert_eq!(rows.len(), 2, "{}", output);
+        assert!(rows.iter().all(|row| row.len() == 4), "{}", output);
+        // The line rises from the bottom left to the top right
+        assert!(rows[0][..2].iter().all(|c| *c == empty) && rows[0][3] != empty, "{}", output);
+        assert!(rows[1][2..].iter().all(|c| *c == empty) && rows[1][0] != empty, "{}", output);
+    }
+}
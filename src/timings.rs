@@ -0,0 +1,37 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How long each stage of rendering took, summed over all equations.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Runs `f`, adding the time it took to `stage`.
+    pub fn record<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+
+        match self.stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((stage, elapsed)),
+        }
+
+        result
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, duration) in &self.stages {
+            writeln!(f, "{:<12} {:>10.3} ms", stage, duration.as_secs_f64() * 1000.0)?;
+        }
+        write!(f, "{:<12} {:>10.3} ms", "total", self.total().as_secs_f64() * 1000.0)
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{GraphSettings, create_root, create_graph, create_graph_3d};
+use crate::plot::{Grid, Surface, sample_2d, draw_grid, surface_3d, draw_surface};
+use crate::error::GraphError;
+use crate::field::parse_field;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The contents of a spec file. Every line is a `key = value` pair, `equation` may be repeated
+/// and lines starting with `#` are ignored:
+///
+/// ```text
+/// path = images/graph.png
+/// xmin = -2
+/// xmax = 2
+/// equation = x^2+y^2=1
+/// equation = y=sin(x)
+/// ```
+pub struct Spec {
+    pub path: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub sim_window: (f64, f64, f64, f64),
+    pub yaw: f64,
+    pub three_d: bool,
+    pub equations: Vec<String>,
+}
+
+impl Spec {
+    pub fn parse(text: &str) -> Result<Spec, String> {
+        let mut spec = Spec {
+            path: "images/graph.png".to_string(),
+            image_width: 600,
+            image_height: 600,
+            sim_window: (-1.0, 1.0, -1.0, 1.0),
+            yaw: 0.5,
+            three_d: false,
+            equations: Vec::new(),
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format!("line {}: expected KEY = VALUE", number + 1))?;
+
+            let number_error = |e: &dyn Error| format!("line {}: invalid value for {}: {}", number + 1, key, e);
+
+            match key {
+                "path" => spec.path = value.to_string(),
+                "width" => spec.image_width = value.parse().map_err(|e| number_error(&e))?,
+                "height" => spec.image_height = value.parse().map_err(|e| number_error(&e))?,
+                "xmin" => spec.sim_window.0 = value.parse().map_err(|e| number_error(&e))?,
+                "xmax" => spec.sim_window.1 = value.parse().map_err(|e| number_error(&e))?,
+                "ymin" => spec.sim_window.2 = value.parse().map_err(|e| number_error(&e))?,
+                "ymax" => spec.sim_window.3 = value.parse().map_err(|e| number_error(&e))?,
+                "yaw" => spec.yaw = value.parse().map_err(|e| number_error(&e))?,
+                "3d" => spec.three_d = value.parse().map_err(|e| number_error(&e))?,
+                "equation" => spec.equations.push(value.to_string()),
+                _ => return Err(format!("line {}: unknown key '{}'", number + 1, key)),
+            }
+        }
+
+        if spec.equations.is_empty() {
+            return Err("the spec contains no equations".to_string());
+        }
+
+        Ok(spec)
+    }
+
+    fn settings(&self) -> GraphSettings<'_> {
+        GraphSettings {
+            path: &self.path,
+            image_width: self.image_width,
+            image_height: self.image_height,
+            sim_window: self.sim_window,
+            yaw: self.yaw,
+            params: &[],
+            refine: None,
+        }
+    }
+}
+
+/// Everything a sampled grid depends on besides the equation text, so that a grid can be reused as
+/// long as none of it changes.
+#[derive(PartialEq, Eq, Hash)]
+struct CacheKey {
+    equation: String,
+    size: (u32, u32),
+    window: [u64; 4],
+}
+
+impl CacheKey {
+    fn new(equation: &str, spec: &Spec) -> CacheKey {
+        let (xmin, xmax, ymin, ymax) = spec.sim_window;
+        CacheKey {
+            equation: equation.to_string(),
+            size: (spec.image_width, spec.image_height),
+            window: [xmin.to_bits(), xmax.to_bits(), ymin.to_bits(), ymax.to_bits()],
+        }
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    grids: HashMap<CacheKey, Grid>,
+    surfaces: HashMap<CacheKey, Surface>,
+}
+
+/// Renders the spec file and then keeps re-rendering it every time it is modified. Errors in the
+/// spec are reported without stopping the watch.
+pub fn watch(spec_path: &Path) -> Result<(), GraphError> {
+    let mut cache = Cache::default();
+    let mut last_modified: Option<SystemTime> = None;
+    let mut missing = false;
+
+    loop {
+        // Editors that save by renaming a temporary file over the spec briefly leave no file at all
+        let modified = match fs::metadata(spec_path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => {
+                missing = false;
+                modified
+            }
+            Err(e) => {
+                if !missing {
+                    eprintln!("{}", GraphError::file(spec_path, e));
+                    missing = true;
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        if last_modified != Some(modified) {
+            last_modified = Some(modified);
+
+            let start = Instant::now();
+
+            match load_and_render(spec_path, &mut cache) {
+                Ok(spec) => println!("Rendered {} in {} ms", spec.path, start.elapsed().as_millis()),
+                Err(e) => eprintln!("{}: {}", spec_path.display(), e),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn load_and_render(spec_path: &Path, cache: &mut Cache) -> Result<Spec, GraphError> {
+    let spec = Spec::parse(&fs::read_to_string(spec_path).map_err(|e| GraphError::file(spec_path, e))?).map_err(GraphError::BadArgument)?;
+    render(&spec, cache)?;
+    Ok(spec)
+}
+
+fn render(spec: &Spec, cache: &mut Cache) -> Result<(), GraphError> {
+    let settings = spec.settings();
+    let root = create_root(&settings)?;
+
+    if spec.three_d {
+        let mut surfaces = HashMap::new();
+        for equation in &spec.equations {
+            let key = CacheKey::new(equation, spec);
+            let surface = match cache.surfaces.remove(&key) {
+                Some(surface) => surface,
+                None => surface_3d(&mut parse_field(equation, &[])?, &settings),
+            };
+            surfaces.insert(key, surface);
+        }
+
+        let mut graph = create_graph_3d(&settings, &root)?;
+        for surface in surfaces.values() {
+            draw_surface(surface, &mut graph)?;
+        }
+
+        // Only keep what the current spec uses so that the cache can't grow without bound
+        cache.surfaces = surfaces;
+    } else {
+        let mut grids = HashMap::new();
+        for equation in &spec.equations {
+            let key = CacheKey::new(equation, spec);
+            let grid = match cache.grids.remove(&key) {
+                Some(grid) => grid,
+                None => sample_2d(&mut parse_field(equation, &[])?, &settings),
+            };
+            grids.insert(key, grid);
+        }
+
+        let mut graph = create_graph(&settings, &root)?;
+        for grid in grids.values() {
+            draw_grid(grid, &settings, &mut graph)?;
+        }
+
+        cache.grids = grids;
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs() {
+        let spec = Spec::parse("# a comment\n\npath = out.png\nwidth = 300\nxmin = -2\nymax = 0.5\n3d = true\nequation = x^2+y^2=1\n  equation=y=sin(x)  \n").unwrap();
+        assert_eq!(spec.path, "out.png");
+        assert_eq!((spec.image_width, spec.image_height), (300, 600));
+        assert_eq!(spec.sim_window, (-2.0, 1.0, -1.0, 0.5));
+        assert!(spec.three_d);
+        // Only the first `=` separates the key, the rest belongs to the equation
+        assert_eq!(spec.equations, ["x^2+y^2=1", "y=sin(x)"]);
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        let error = |text| Spec::parse(text).err().unwrap();
+        assert_eq!(error("equation = x=y\nwidth"), "line 2: expected KEY = VALUE");
+        assert_eq!(error("equation = x=y\ncolour = red"), "line 2: unknown key 'colour'");
+        assert!(error("xmin = left\nequation = x=y").starts_with("line 1: invalid value for xmin"));
+        assert!(error("3d = yes\nequation = x=y").starts_with("line 1: invalid value for 3d"));
+        assert_eq!(error("# nothing to plot\nxmin = 0"), "the spec contains no equations");
+    }
+}
@@ -0,0 +1,50 @@
+//! Runs the command line tool and checks the exit codes and warnings it reports.
+
+use std::env;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn graph(args: &[&str]) -> Output {
+    // Tests run in parallel, so each run gets its own image
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!("graph-cli-test-{}-{}.png", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed)));
+    Command::new(env!("CARGO_BIN_EXE_graph"))
+        .args(args)
+        .arg("--path")
+        .arg(&path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn malformed_equations_are_parse_errors() {
+    for equation in ["x^2+=y", "sin(x=y", "(x+y"] {
+        for mode in [&[][..], &["--explicit"][..], &["--3d"][..]] {
+            let output = graph(&[mode, &["--equation", equation]].concat());
+            assert_eq!(output.status.code(), Some(3), "{} with {:?}: {}", equation, mode, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+}
+
+#[test]
+fn turntables_need_frames() {
+    let output = graph(&["--turntable", "0"]);
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn holes_are_reported() {
+    let output = graph(&["--equation", "y=sqrt(x)"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("warning: "));
+
+    let output = graph(&["--equation", "y=x"]);
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn missing_tangents_are_reported() {
+    let output = graph(&["--explicit", "--equation", "y=ln(x)", "--tangent=-1", "--normal=1"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "warning: y=ln(x): no tangent at x = -1\n");
+}
@@ -0,0 +1,115 @@
+//! Renders a few fields and compares them with the reference images in `tests/golden`.
+//!
+//! A missing reference fails the test. Set `UPDATE_GOLDEN=1` to write the references for new cases,
+//! or rewrite all of them after an intended change to the output, and commit them. On a mismatch
+//! the rendered image is saved next to the reference as `NAME.actual.png`.
+
+use graph::plot::plot_field;
+use graph::{create_graph, GraphSettings};
+use image::{GrayImage, RgbImage};
+use plotters::prelude::*;
+
+use std::env;
+use std::path::PathBuf;
+
+const WIDTH: u32 = 200;
+const HEIGHT: u32 = 200;
+
+/// Pixels whose blurred brightness differs by more than this count as changed.
+const PIXEL_TOLERANCE: i32 = 32;
+/// The fraction of pixels allowed to change, which absorbs font rendering differences between
+/// machines but not a curve moving or disappearing.
+const CHANGED_TOLERANCE: f64 = 0.002;
+
+fn render(mut field: impl FnMut(f64, f64) -> f64, sim_window: (f64, f64, f64, f64)) -> RgbImage {
+    let mut buffer = vec![0; (WIDTH * HEIGHT * 3) as usize];
+
+    let settings = GraphSettings {
+        path: "",
+        image_width: WIDTH,
+        image_height: HEIGHT,
+        sim_window,
+        yaw: 0.5,
+        params: &[],
+        refine: None,
+    };
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).unwrap();
+        let mut chart = create_graph(&settings, &root).unwrap();
+        plot_field(&mut field, &settings, &mut chart).unwrap();
+        root.present().unwrap();
+    }
+
+    RgbImage::from_raw(WIDTH, HEIGHT, buffer).unwrap()
+}
+
+/// Brightness with a 3x3 box blur, so that anti-aliasing shifting by a pixel isn't a change.
+fn blurred(image: &RgbImage) -> GrayImage {
+    let (width, height) = image.dimensions();
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut sum = 0;
+        let mut count = 0;
+        for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                let [r, g, b] = image.get_pixel(nx, ny).0;
+                sum += (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+                count += 1;
+            }
+        }
+        image::Luma([(sum / count) as u8])
+    })
+}
+
+fn check(name: &str, actual: RgbImage) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let path = dir.join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(&dir).unwrap();
+        actual.save(&path).unwrap();
+        eprintln!("wrote reference image {}", path.display());
+        return;
+    }
+
+    assert!(path.exists(), "{} has no reference image, run with UPDATE_GOLDEN=1 to write it", name);
+
+    let expected = image::open(&path).unwrap().to_rgb8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{} changed size", name);
+
+    let (a, b) = (blurred(&expected), blurred(&actual));
+    let changed = a.pixels().zip(b.pixels()).filter(|(a, b)| (a.0[0] as i32 - b.0[0] as i32).abs() > PIXEL_TOLERANCE).count();
+    let fraction = changed as f64 / (WIDTH * HEIGHT) as f64;
+
+    if fraction > CHANGED_TOLERANCE {
+        let actual_path = dir.join(format!("{}.actual.png", name));
+        actual.save(&actual_path).unwrap();
+        panic!("{}: {:.2}% of the pixels changed, see {}", name, fraction * 100.0, actual_path.display());
+    }
+}
+
+#[test]
+fn circle() {
+    check("circle", render(|x, y| x * x + y * y - 0.5, (-1.0, 1.0, -1.0, 1.0)));
+}
+
+#[test]
+fn parabola_off_centre() {
+    check("parabola_off_centre", render(|x, y| x * x - y, (-1.0, 3.0, -0.5, 4.0)));
+}
+
+#[test]
+fn double_root() {
+    check("double_root", render(|x, y| (y - x * x).powi(2), (-1.0, 1.0, -1.0, 1.0)));
+}
+
+#[test]
+fn pole() {
+    check("pole", render(|x, y| x.tan() - y, (-3.0, 3.0, -3.0, 3.0)));
+}
+
+#[test]
+fn hole_outside_domain() {
+    check("hole_outside_domain", render(|x, y| x.ln() - y, (-2.0, 2.0, -2.0, 2.0)));
+}